@@ -1,5 +1,6 @@
 #![allow(dead_code, unused_imports)]
 
+extern crate lewton;
 extern crate rand;
 extern crate sdl2;
 extern crate sdl2_image;
@@ -8,9 +9,196 @@ extern crate sdl2_ttf;
 mod phi;
 mod views;
 
+use ::phi::{Phi, View};
+use ::phi::actions::Bindings;
+use ::phi::audio::AudioOutput;
+use ::phi::replay::Recording;
+use ::phi::window::WindowConfig;
+
+
+/// Where the options of the window are read from, if the file exists.
+const WINDOW_CONFIG_PATH: &'static str = "window.cfg";
+
+/// Where the keys bound to the actions are read from, and saved to.
+const BINDINGS_PATH: &'static str = "bindings.cfg";
+
 
 fn main() {
-    ::phi::spawn("ArcadeRS Shooter", |phi| {
-        Box::new(::views::main_menu::MainMenuView::new(phi))
-    });
+    let mut config = ::phi::Config::new();
+    let mut headless_steps = None;
+    let mut window_path = None;
+    let mut window_overrides = vec![];
+    let mut bindings_path = BINDINGS_PATH.to_string();
+    let mut follow_mouse = false;
+
+    // `--render-audio out.wav` writes the sounds to a file instead of playing
+    // them, and `--no-audio` discards them. `--headless 600` runs 600 steps
+    // without opening a window, and exits. `--record session.phir` saves the
+    // input to a file, which `--replay session.phir` plays back. `--seed 42`
+    // makes the random events the same on every run. `--trace game.trace`
+    // writes a hash of the game's state for every frame, and `--verify
+    // game.trace` reports the first frame which differs from such a trace.
+    // `--window-config my.cfg` reads the options of the window from another
+    // file than `window.cfg`, and `--size 1280x720`, `--fullscreen`,
+    // `--borderless`, `--windowed`, `--vsync`, `--frame-cap 60` and
+    // `--software` override them. `--scaling integer` only scales the game by
+    // whole factors. `--bindings keys.cfg` reads and saves the keys bound to
+    // the actions to another file than `bindings.cfg`, and `--follow-mouse`
+    // makes the ship follow the mouse.
+    let mut args = ::std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--render-audio" => match args.next() {
+                Some(path) => config.audio = AudioOutput::File(path),
+                None => panic!("--render-audio expects the path of a WAV file"),
+            },
+            "--no-audio" => config.audio = AudioOutput::Null,
+            "--seed" => match args.next().and_then(|seed| seed.parse().ok()) {
+                Some(seed) => config.seed = Some(seed),
+                None => panic!("--seed expects a positive integer"),
+            },
+            "--record" => match args.next() {
+                Some(path) => config.record = Some(path),
+                None => panic!("--record expects the path of the replay file"),
+            },
+            "--replay" => match args.next().map(|path| Recording::load(&path)) {
+                Some(Ok(recording)) => config.replay = Some(recording),
+                Some(Err(e)) => panic!("{}", e),
+                None => panic!("--replay expects the path of a replay file"),
+            },
+            "--trace" => match args.next() {
+                Some(path) => config.trace = Some(path),
+                None => panic!("--trace expects the path of the trace file"),
+            },
+            "--verify" => match args.next() {
+                Some(path) => config.verify = Some(path),
+                None => panic!("--verify expects the path of a trace file"),
+            },
+            "--window-config" => match args.next() {
+                Some(path) => window_path = Some(path),
+                None => panic!("--window-config expects the path of a configuration file"),
+            },
+            "--size" => match args.next() {
+                Some(ref size) if size.contains('x') => {
+                    let mut parts = size.splitn(2, 'x');
+                    window_overrides.push(("width", parts.next().unwrap().to_string()));
+                    window_overrides.push(("height", parts.next().unwrap().to_string()));
+                },
+                _ => panic!("--size expects a size such as 1280x720"),
+            },
+            "--fullscreen" => window_overrides.push(("mode", "fullscreen".to_string())),
+            "--borderless" => window_overrides.push(("mode", "borderless".to_string())),
+            "--windowed" => window_overrides.push(("mode", "windowed".to_string())),
+            "--vsync" => window_overrides.push(("vsync", "true".to_string())),
+            "--frame-cap" => match args.next() {
+                Some(cap) => window_overrides.push(("frame_cap", cap)),
+                None => panic!("--frame-cap expects a number of frames per second"),
+            },
+            "--software" => window_overrides.push(("renderer", "software".to_string())),
+            "--scaling" => match args.next() {
+                Some(mode) => window_overrides.push(("scaling", mode)),
+                None => panic!("--scaling expects integer or aspect"),
+            },
+            "--bindings" => match args.next() {
+                Some(path) => bindings_path = path,
+                None => panic!("--bindings expects the path of a bindings file"),
+            },
+            "--follow-mouse" => follow_mouse = true,
+            "--headless" => match args.next().and_then(|steps| steps.parse().ok()) {
+                Some(steps) => headless_steps = Some(steps),
+                None => panic!("--headless expects a number of steps"),
+            },
+            _ => println!("Ignoring unknown argument: {}", arg),
+        }
+    }
+
+    // The default file is optional, unlike one given explicitly.
+    config.window = match window_path {
+        Some(path) => WindowConfig::load(&path).unwrap_or_else(|e| panic!("{}", e)),
+        None if ::std::path::Path::new(WINDOW_CONFIG_PATH).exists() =>
+            WindowConfig::load(WINDOW_CONFIG_PATH).unwrap_or_else(|e| panic!("{}", e)),
+        None => WindowConfig::new(),
+    };
+
+    for (key, value) in window_overrides {
+        if let Err(e) = config.window.set(key, &value) {
+            panic!("{}", e);
+        }
+    }
+
+    if ::std::path::Path::new(&bindings_path).exists() {
+        config.bindings = Bindings::load(&bindings_path).unwrap_or_else(|e| panic!("{}", e));
+    }
+    for conflict in config.bindings.conflicts() {
+        println!("Warning: {}", conflict);
+    }
+    config.bindings_path = Some(bindings_path);
+    if follow_mouse {
+        config.bindings.follow_mouse = true;
+    }
+
+    match headless_steps {
+        Some(steps) => ::phi::spawn_headless(config, steps, init, |phi, view| {
+            println!("Ran {} steps, {} sounds playing, {}",
+                     steps, phi.audio.active_voices(),
+                     if view.is_some() { "still running" } else { "quit" });
+
+            // Lets scripts tell that the game changed.
+            if phi.trace.divergence().is_some() {
+                ::std::process::exit(1);
+            }
+        }),
+        None => ::phi::spawn_with("ArcadeRS Shooter", config, init),
+    }
+}
+
+
+fn init(phi: &mut Phi) -> Box<View> {
+    // Decode every sound before the game starts, so that a missing file is
+    // reported right away rather than when it is first played.
+    if let Err(e) = phi.sounds.preload(::views::game::SOUNDS) {
+        panic!("Unable to load the game's sounds: {}", e);
+    }
+
+    Box::new(::views::main_menu::MainMenuView::new(phi))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::init;
+    use ::phi::audio::AudioOutput;
+    use ::phi::audio::wav;
+    use ::phi::input::Script;
+    use ::phi::testing;
+    use ::phi::timing::DEFAULT_STEP_RATE;
+    use ::std::fs::File;
+    use ::views::main_menu::MainMenuView;
+
+    /// Two seconds at the default step rate.
+    const STEPS: u32 = 240;
+
+    #[test]
+    fn renders_the_audio_to_a_wav_file() {
+        let path = ::std::env::temp_dir().join("arcade-rs-headless.wav")
+            .to_str().unwrap().to_string();
+        let mut config = testing::config(Script::new());
+        config.audio = AudioOutput::File(path.clone());
+        testing::run(config, STEPS, init, |_, _| ());
+
+        // Every step renders its share of the audio, whatever is playing.
+        let header = wav::read_header(&mut File::open(&path).unwrap()).unwrap();
+        let expected = STEPS as f64 / DEFAULT_STEP_RATE * header.spec.rate as f64;
+        assert_eq!(header.spec.channels, 2);
+        assert!((header.frames() as f64 - expected).abs() <= 1.0);
+    }
+
+    #[test]
+    fn keeps_running_headless() {
+        // Without any input, the game is still on the main menu. `top` fails
+        // if it quit instead.
+        testing::run(testing::config(Script::new()), STEPS, init, |_, view| {
+            testing::top::<MainMenuView>(view);
+        });
+    }
 }
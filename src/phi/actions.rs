@@ -0,0 +1,491 @@
+//! What the player means by pressing a key or a button. Views ask whether an
+//! action is requested rather than whether a given key is pressed, so that the
+//! player may bind every action to the keys and buttons of their choice.
+//!
+//! The bindings may be read from, and saved to, a file with one line per
+//! action, listing the names of its inputs: keys as they appear in `KEY_NAMES`,
+//! then buttons and halves of axes of the controllers, prefixed with `pad_`.
+//! The deadzone of the sticks is set there as well, and whether the ship
+//! follows the mouse:
+//!
+//! ```text
+//! move_up = Up, W, pad_dpup, pad_lefty-
+//! fire = Space, LCtrl, pad_a
+//! deadzone = 0.25
+//! follow_mouse = false
+//! ```
+
+use ::phi::{Events, KEY_NAMES};
+use ::phi::gamepad::{self, AXIS_NAMES, BUTTON_NAMES};
+use ::std::fs::File;
+use ::std::io::{Read, Write};
+
+
+/// The actions which can be bound to inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp = 0,
+    MoveDown = 1,
+    MoveLeft = 2,
+    MoveRight = 3,
+    Fire = 4,
+    SelectWeapon1 = 5,
+    SelectWeapon2 = 6,
+    SelectWeapon3 = 7,
+
+    /// Chooses the selected entry of a menu.
+    Confirm = 8,
+
+    /// Pauses the game, or resumes it.
+    Pause = 9,
+
+    /// Leaves a menu, or quits from the main menu.
+    Back = 10,
+
+    ToggleFullscreen = 11,
+}
+
+pub const ACTIONS: &'static [Action] = &[
+    Action::MoveUp, Action::MoveDown, Action::MoveLeft, Action::MoveRight,
+    Action::Fire, Action::SelectWeapon1, Action::SelectWeapon2, Action::SelectWeapon3,
+    Action::Confirm, Action::Pause, Action::Back, Action::ToggleFullscreen,
+];
+
+
+/// Something the player can press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    /// A key, by index in `KEY_NAMES`.
+    Key(usize),
+
+    /// A button of the controllers, by index in `gamepad::BUTTONS`.
+    Button(usize),
+
+    /// Half of an axis of the controllers, by index in `gamepad::AXES`: the
+    /// positive one if the flag is set, the negative one otherwise.
+    Axis(usize, bool),
+}
+
+
+/// Where an action is used. The same input may be bound to actions which are
+/// never used at the same time, such as firing and choosing a menu entry.
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Menus,
+    Game,
+    Everywhere,
+}
+
+
+/// The inputs bound to every action.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    /// The inputs of every action, indexed by the action.
+    inputs: Vec<Vec<Input>>,
+
+    /// The fraction of their course the sticks can move without any effect.
+    pub deadzone: f64,
+
+    /// Whether the ship follows the mouse and fires when its left button is
+    /// pressed, besides obeying the other inputs.
+    pub follow_mouse: bool,
+
+    /// Whether the bindings changed since they were loaded or saved.
+    changed: bool,
+}
+
+
+/// An input bound to two actions which may be requested at the same time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conflict {
+    pub input: Input,
+    pub actions: (Action, Action),
+}
+
+
+impl Action {
+    /// The name of the action in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Fire => "fire",
+            Action::SelectWeapon1 => "select_weapon_1",
+            Action::SelectWeapon2 => "select_weapon_2",
+            Action::SelectWeapon3 => "select_weapon_3",
+            Action::Confirm => "confirm",
+            Action::Pause => "pause",
+            Action::Back => "back",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().cloned().find(|action| action.name() == name)
+    }
+
+    fn scope(self) -> Scope {
+        match self {
+            // Also used to move through the menus.
+            Action::MoveUp | Action::MoveDown => Scope::Everywhere,
+
+            Action::MoveLeft | Action::MoveRight | Action::Fire |
+            Action::SelectWeapon1 | Action::SelectWeapon2 | Action::SelectWeapon3 => Scope::Game,
+
+            // Also closes the pause menu, where it means the same as going
+            // back.
+            Action::Pause => Scope::Game,
+            Action::Confirm | Action::Back => Scope::Menus,
+            Action::ToggleFullscreen => Scope::Everywhere,
+        }
+    }
+
+    /// Whether binding both actions to the same input would be ambiguous.
+    fn overlaps(self, other: Action) -> bool {
+        self != other && (self.scope() == other.scope() ||
+                          self.scope() == Scope::Everywhere ||
+                          other.scope() == Scope::Everywhere)
+    }
+}
+
+
+impl Input {
+    /// The name of the input in configuration files.
+    pub fn name(self) -> String {
+        match self {
+            Input::Key(key) => KEY_NAMES[key].to_string(),
+            Input::Button(button) => format!("pad_{}", BUTTON_NAMES[button]),
+            Input::Axis(axis, positive) =>
+                format!("pad_{}{}", AXIS_NAMES[axis], if positive { "+" } else { "-" }),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Input> {
+        if !name.starts_with("pad_") {
+            return KEY_NAMES.iter().position(|&key| key == name).map(Input::Key);
+        }
+
+        let name = &name[4..];
+        if let Some(button) = BUTTON_NAMES.iter().position(|&button| button == name) {
+            return Some(Input::Button(button));
+        }
+
+        let positive = if name.ends_with('+') { true }
+                       else if name.ends_with('-') { false }
+                       else { return None };
+
+        let name = &name[..name.len() - 1];
+        AXIS_NAMES.iter().position(|&axis| axis == name).map(|axis| Input::Axis(axis, positive))
+    }
+
+    /// How far the input is pushed, between 0 and 1.
+    fn value(self, events: &Events, deadzone: f64) -> f64 {
+        match self {
+            Input::Key(key) => if events.is_held(key) { 1.0 } else { 0.0 },
+            Input::Button(button) => if events.pad.buttons[button] { 1.0 } else { 0.0 },
+            Input::Axis(axis, positive) => half(events.pad.axis(axis, deadzone), positive),
+        }
+    }
+
+    /// Whether the input was pressed (`Some(true)`) or released
+    /// (`Some(false)`) during the frame. A half of an axis counts as pressed
+    /// once it is pushed past `gamepad::PRESS_THRESHOLD`.
+    fn change(self, events: &Events, deadzone: f64) -> Option<bool> {
+        match self {
+            Input::Key(key) => events.now.key(key),
+            Input::Button(button) => events.now.buttons.iter()
+                .filter(|&&(changed, _)| changed == button)
+                .map(|&(_, pressed)| pressed)
+                .last(),
+            Input::Axis(axis, positive) => {
+                let before = half(events.pad.axis_before(axis, deadzone), positive) >= gamepad::PRESS_THRESHOLD;
+                let after = half(events.pad.axis(axis, deadzone), positive) >= gamepad::PRESS_THRESHOLD;
+                if before != after { Some(after) } else { None }
+            },
+        }
+    }
+}
+
+
+impl Bindings {
+    /// The default bindings: the arrows, WASD, the D-pad or the left stick to
+    /// move, Space or A to fire, Escape, P or Start to pause, and Escape or B
+    /// to leave the menus.
+    pub fn new() -> Bindings {
+        let mut bindings = Bindings {
+            inputs: vec![vec![]; ACTIONS.len()],
+            deadzone: gamepad::DEFAULT_DEADZONE,
+            follow_mouse: false,
+            changed: false,
+        };
+
+        let defaults: &[(Action, &[&str])] = &[
+            (Action::MoveUp, &["Up", "W", "pad_dpup", "pad_lefty-"]),
+            (Action::MoveDown, &["Down", "S", "pad_dpdown", "pad_lefty+"]),
+            (Action::MoveLeft, &["Left", "A", "pad_dpleft", "pad_leftx-"]),
+            (Action::MoveRight, &["Right", "D", "pad_dpright", "pad_leftx+"]),
+            (Action::Fire, &["Space", "pad_a", "pad_righttrigger+"]),
+            (Action::SelectWeapon1, &["Num1", "pad_x"]),
+            (Action::SelectWeapon2, &["Num2", "pad_y"]),
+            (Action::SelectWeapon3, &["Num3", "pad_b"]),
+            (Action::Confirm, &["Return", "Space", "pad_a"]),
+            (Action::Pause, &["Escape", "P", "pad_start"]),
+            (Action::Back, &["Escape", "pad_b"]),
+            (Action::ToggleFullscreen, &["F11"]),
+        ];
+
+        for &(action, names) in defaults {
+            bindings.inputs[action as usize] = names.iter()
+                .map(|name| Input::from_name(name).expect("Unknown default input"))
+                .collect();
+        }
+
+        bindings
+    }
+
+    /// Reads the bindings saved at `path`. The actions which are not listed
+    /// keep their default inputs.
+    pub fn load(path: &str) -> Result<Bindings, String> {
+        let mut contents = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("Could not open the bindings {}: {}", path, e)));
+
+        Bindings::decode(&contents, path)
+    }
+
+    /// Reads bindings in the format of the files, for instance as stored in a
+    /// replay. `path` tells where they come from in error messages.
+    pub fn decode(contents: &str, path: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = try!(parts.next()
+                .ok_or_else(|| format!("{}:{}: Expected `action = inputs`", path, i + 1)))
+                .trim();
+
+            if name == "deadzone" {
+                bindings.deadzone = match value.parse::<f64>() {
+                    Ok(deadzone) if deadzone >= 0.0 && deadzone < 1.0 => deadzone,
+                    _ => return Err(format!("{}:{}: Expected a deadzone between 0 and 1, got {}",
+                                            path, i + 1, value)),
+                };
+                continue;
+            }
+
+            if name == "follow_mouse" {
+                bindings.follow_mouse = match value.parse::<bool>() {
+                    Ok(follow_mouse) => follow_mouse,
+                    Err(_) => return Err(format!("{}:{}: Expected true or false, got {}",
+                                                 path, i + 1, value)),
+                };
+                continue;
+            }
+
+            let action = try!(Action::from_name(name)
+                .ok_or_else(|| format!("{}:{}: Unknown action: {}", path, i + 1, name)));
+
+            let mut inputs = vec![];
+            for input_name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                inputs.push(try!(Input::from_name(input_name)
+                    .ok_or_else(|| format!("{}:{}: Unknown input: {}", path, i + 1, input_name))));
+            }
+            bindings.inputs[action as usize] = inputs;
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn save(&mut self, path: &str) -> Result<(), String> {
+        let contents = self.encode();
+        try!(File::create(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Could not save the bindings to {}: {}", path, e)));
+
+        self.changed = false;
+        Ok(())
+    }
+
+    /// The bindings in the format of the files.
+    pub fn encode(&self) -> String {
+        let mut contents = String::new();
+        for &action in ACTIONS {
+            let names: Vec<String> = self.inputs(action).iter().map(|input| input.name()).collect();
+            contents.push_str(&format!("{} = {}\n", action.name(), names.join(", ")));
+        }
+        contents.push_str(&format!("deadzone = {}\n", self.deadzone));
+        contents.push_str(&format!("follow_mouse = {}\n", self.follow_mouse));
+        contents
+    }
+
+    /// Whether the bindings changed since they were loaded or saved.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn inputs(&self, action: Action) -> &[Input] {
+        &self.inputs[action as usize]
+    }
+
+    /// Adds `input` to the inputs of `action`. Returns the actions which were
+    /// already bound to it, and which it now makes ambiguous.
+    pub fn bind(&mut self, action: Action, input: Input) -> Vec<Action> {
+        if !self.inputs[action as usize].contains(&input) {
+            self.inputs[action as usize].push(input);
+            self.changed = true;
+        }
+
+        ACTIONS.iter().cloned()
+            .filter(|&other| action.overlaps(other) && self.inputs(other).contains(&input))
+            .collect()
+    }
+
+    pub fn unbind(&mut self, action: Action, input: Input) {
+        let inputs = &mut self.inputs[action as usize];
+        if let Some(position) = inputs.iter().position(|&bound| bound == input) {
+            inputs.remove(position);
+            self.changed = true;
+        }
+    }
+
+    /// Every input which is bound to ambiguous actions.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = vec![];
+
+        for (i, &first) in ACTIONS.iter().enumerate() {
+            for &second in &ACTIONS[i + 1..] {
+                if !first.overlaps(second) {
+                    continue;
+                }
+
+                for &input in self.inputs(first) {
+                    if self.inputs(second).contains(&input) {
+                        conflicts.push(Conflict {
+                            input: input,
+                            actions: (first, second),
+                        });
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Whether one of the inputs of `action` was pressed during the frame.
+    pub fn pressed(&self, events: &Events, action: Action) -> bool {
+        self.inputs(action).iter().any(|input| input.change(events, self.deadzone) == Some(true))
+    }
+
+    /// Whether one of the inputs of `action` was released during the frame.
+    pub fn released(&self, events: &Events, action: Action) -> bool {
+        self.inputs(action).iter().any(|input| input.change(events, self.deadzone) == Some(false))
+    }
+
+    /// Whether one of the inputs of `action` is held down, or pushed out of
+    /// the deadzone.
+    pub fn held(&self, events: &Events, action: Action) -> bool {
+        self.strength(events, action) > 0.0
+    }
+
+    /// How much `action` is requested, between 0 and 1. Keys and buttons are
+    /// either 0 or 1, while sticks may be pushed partially.
+    pub fn strength(&self, events: &Events, action: Action) -> f64 {
+        self.inputs(action).iter()
+            .map(|input| input.value(events, self.deadzone))
+            .fold(0.0, f64::max)
+    }
+}
+
+
+impl ::std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{} is bound to both {} and {}",
+               self.input.name(), self.actions.0.name(), self.actions.1.name())
+    }
+}
+
+
+/// The part of `value` on the positive or negative side, as a positive number.
+fn half(value: f64, positive: bool) -> f64 {
+    if positive { value.max(0.0) } else { (-value).max(0.0) }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Bindings, Input};
+    use ::phi::{Phi, View, ViewAction};
+    use ::phi::input::{self, Script};
+    use ::phi::testing;
+    use ::sdl2::controller::Axis;
+
+    #[test]
+    fn default_bindings_do_not_conflict() {
+        // Escape pauses the game and leaves the menus, which are never both
+        // requested at once.
+        let bindings = Bindings::new();
+        let escape = Input::from_name("Escape").unwrap();
+        assert!(bindings.inputs(Action::Pause).contains(&escape));
+        assert!(bindings.inputs(Action::Back).contains(&escape));
+        assert_eq!(bindings.conflicts(), vec![]);
+    }
+
+    #[test]
+    fn reports_a_conflict() {
+        let mut bindings = Bindings::new();
+        let conflicts = bindings.bind(Action::Fire, Input::from_name("Up").unwrap());
+        assert_eq!(conflicts, vec![Action::MoveUp]);
+        assert_eq!(bindings.conflicts().len(), 1);
+    }
+
+    /// Remembers whether moving down was pressed or released on every step.
+    struct Recorder {
+        steps: Vec<(bool, bool)>,
+    }
+
+    impl View for Recorder {
+        fn update(&mut self, phi: &mut Phi, _: f64) -> ViewAction {
+            self.steps.push((phi.pressed(Action::MoveDown), phi.released(Action::MoveDown)));
+            ViewAction::None
+        }
+
+        fn render(&self, _: &mut Phi, _: f64) {}
+    }
+
+    fn recorder(_: &mut Phi) -> Box<View> {
+        Box::new(Recorder { steps: vec![] })
+    }
+
+    #[test]
+    fn stick_is_pressed_past_the_threshold() {
+        // With the default deadzone, 40% of the course is below the press
+        // threshold, and 80% above it.
+        let script = Script::new()
+            .at(1, input::axis_motion(Axis::LeftY, 13_107))
+            .at(2, input::axis_motion(Axis::LeftY, 32_767))
+            .at(3, input::axis_motion(Axis::LeftY, 26_214))
+            .at(4, input::axis_motion(Axis::LeftY, 0));
+
+        let steps = testing::run(testing::config(script), 6, recorder, |_, view| {
+            testing::top::<Recorder>(view).steps.clone()
+        });
+
+        assert_eq!(steps, vec![
+            (false, false),
+            (false, false),
+            (true, false),
+            (false, false),
+            (false, true),
+            (false, false),
+        ]);
+    }
+}
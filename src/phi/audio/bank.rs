@@ -0,0 +1,155 @@
+use super::decoder;
+use super::mixer::Bus;
+use super::synth::SfxParams;
+use ::sdl2::audio::AudioSpec;
+use ::std::collections::HashMap;
+use ::std::sync::Arc;
+
+
+/// Limits how often a sound may be played, so that the same effect triggered
+/// many times at once does not drown everything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundSettings {
+    /// The bus whose volume applies to the sound.
+    pub bus: Bus,
+
+    /// The number of voices which may play this sound at the same time. Once
+    /// it is reached, the oldest of them is restarted.
+    pub max_voices: usize,
+
+    /// When the mixer runs out of voices, it interrupts the one with the
+    /// lowest priority, as long as it is not higher than the new sound's.
+    /// Sounds with a high priority may also duck the music.
+    pub priority: u8,
+
+    /// The minimum time between two starts of this sound, in seconds. The sound
+    /// is ignored if it is played again sooner than that.
+    pub cooldown: f64,
+}
+
+impl SoundSettings {
+    pub fn new() -> SoundSettings {
+        SoundSettings {
+            bus: Bus::Sfx,
+            max_voices: 8,
+            priority: 0,
+            cooldown: 0.0,
+        }
+    }
+}
+
+
+/// A cheap handle to samples decoded or synthesized by a `SoundBank`. Cloning it does not
+/// copy the samples.
+#[derive(Clone)]
+pub struct Sound {
+    id: usize,
+    samples: Arc<Vec<f32>>,
+    pub settings: SoundSettings,
+}
+
+impl Sound {
+    /// Identifies the sound among the ones of the bank which created it.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns a handle to the same samples, played with other settings.
+    pub fn with_settings(self, settings: SoundSettings) -> Sound {
+        Sound {
+            settings: settings,
+            ..self
+        }
+    }
+
+    /// Interleaved samples, in the format of the device.
+    pub fn samples(&self) -> Arc<Vec<f32>> {
+        self.samples.clone()
+    }
+
+    /// The length of the sound, in seconds, when played by a device opened
+    /// with `spec`.
+    pub fn duration(&self, spec: AudioSpec) -> f64 {
+        self.samples.len() as f64 / (spec.channels as f64 * spec.freq as f64)
+    }
+}
+
+
+/// Decodes every sound file once and converts it to the output format of the
+/// device, so that playing it later does not touch the disk. Any integer or
+/// float WAV file, as well as any Ogg Vorbis file, can be loaded, whatever its
+/// frequency and channel count.
+pub struct SoundBank {
+    spec: AudioSpec,
+    cache: HashMap<String, Sound>,
+}
+
+impl SoundBank {
+    /// Creates an empty bank whose sounds will be converted for a device
+    /// opened with `spec`.
+    pub fn new(spec: AudioSpec) -> SoundBank {
+        SoundBank {
+            spec: spec,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Returns the sound located at `path`, decoding it if it was not
+    /// already in the bank.
+    pub fn load(&mut self, path: &str) -> Result<Sound, String> {
+        if let Some(sound) = self.cache.get(path) {
+            return Ok(sound.clone());
+        }
+
+        let sound = Sound {
+            id: self.cache.len(),
+            samples: Arc::new(try!(decoder::decode_all(
+                path, self.spec.channels as usize, self.spec.freq as u32))),
+            settings: SoundSettings::new(),
+        };
+
+        self.cache.insert(path.to_string(), sound.clone());
+        Ok(sound)
+    }
+
+    /// Returns the sound effect rendered from `params`, rendering it if no
+    /// sound named `name` is in the bank yet.
+    pub fn synthesize(&mut self, name: &str, params: &SfxParams) -> Sound {
+        if let Some(sound) = self.cache.get(name) {
+            return sound.clone();
+        }
+
+        let sound = Sound {
+            id: self.cache.len(),
+            samples: Arc::new(params.render(self.spec.channels as usize, self.spec.freq as u32)),
+            settings: SoundSettings::new(),
+        };
+
+        self.cache.insert(name.to_string(), sound.clone());
+        sound
+    }
+
+    /// Changes the settings with which the sound located at `path` is played,
+    /// loading it if necessary. Only the handles returned afterwards use the
+    /// new settings.
+    pub fn configure(&mut self, path: &str, settings: SoundSettings) -> Result<Sound, String> {
+        let sound = try!(self.load(path)).with_settings(settings);
+        self.cache.insert(path.to_string(), sound.clone());
+        Ok(sound)
+    }
+
+    /// Loads every sound in `paths`, stopping at the first one which could
+    /// not be decoded.
+    pub fn preload(&mut self, paths: &[&str]) -> Result<(), String> {
+        for path in paths {
+            try!(self.load(path));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sound located at `path` if it was already loaded.
+    pub fn get(&self, path: &str) -> Option<Sound> {
+        self.cache.get(path).cloned()
+    }
+}
@@ -0,0 +1,276 @@
+//! Turns PCM data of any common layout into the interleaved `f32` samples
+//! used by the mixer, at the frequency and channel count of the device.
+
+use ::std::f64::consts::PI;
+
+
+/// The number of input frames considered on each side of an output frame by
+/// the resampler. Higher values filter better, but cost more.
+const HALF_TAPS: usize = 16;
+
+
+/// The way in which a single sample is encoded. Multi-byte formats are
+/// little-endian, as in WAV files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    S16,
+    S24,
+    S32,
+    F32,
+}
+
+impl SampleFormat {
+    /// The number of bytes used to store a single sample.
+    pub fn bytes(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::S16 => 2,
+            SampleFormat::S24 => 3,
+            SampleFormat::S32 | SampleFormat::F32 => 4,
+        }
+    }
+}
+
+
+/// Describes some PCM data, as found in the header of a sound file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PcmSpec {
+    pub format: SampleFormat,
+    pub channels: usize,
+    pub rate: u32,
+}
+
+
+/// Decodes raw interleaved samples into floats in the range `[-1, 1]`. Bytes
+/// which do not form a complete sample at the end of `bytes` are ignored.
+pub fn decode_samples(format: SampleFormat, bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks(format.bytes())
+        .filter(|chunk| chunk.len() == format.bytes())
+        .map(|b| match format {
+            SampleFormat::U8 =>
+                (b[0] as f32 - 128.0) / 128.0,
+
+            SampleFormat::S16 =>
+                ((b[0] as u16 | (b[1] as u16) << 8) as i16) as f32 / 32_768.0,
+
+            SampleFormat::S24 => {
+                // Shift the value to the top of an i32 to keep its sign.
+                let v = ((b[0] as u32) << 8 | (b[1] as u32) << 16 | (b[2] as u32) << 24) as i32;
+                (v >> 8) as f32 / 8_388_608.0
+            },
+
+            SampleFormat::S32 => {
+                let v = (b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24) as i32;
+                v as f32 / 2_147_483_648.0
+            },
+
+            SampleFormat::F32 => {
+                let bits = b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24;
+                let v = unsafe { ::std::mem::transmute::<u32, f32>(bits) };
+                v.max(-1.0).min(1.0)
+            },
+        })
+        .collect()
+}
+
+
+/// Converts interleaved samples from `from` channels to `to` channels. Mono is
+/// copied to every output channel, and surplus channels are averaged into the
+/// output channel of the same parity, so that stereo folds down to mono.
+pub fn remix_channels(samples: &[f32], from: usize, to: usize) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+
+    let frames = samples.len() / from;
+    let mut out = Vec::with_capacity(frames * to);
+
+    for frame in samples.chunks(from).take(frames) {
+        for c in 0..to {
+            if from < to {
+                out.push(frame[c % from]);
+            } else {
+                let mut sum = 0.0;
+                let mut count = 0;
+                for i in (c..from).filter(|i| i % to == c) {
+                    sum += frame[i];
+                    count += 1;
+                }
+                out.push(sum / count as f32);
+            }
+        }
+    }
+
+    out
+}
+
+
+/// Band-limited resampler based on a Blackman-windowed sinc. It keeps the end
+/// of the previous input around, so that a long sound may be converted one
+/// chunk at a time without clicks at the boundaries.
+pub struct Resampler {
+    channels: usize,
+
+    /// The number of input frames that make up a single output frame.
+    step: f64,
+
+    /// The normalized cutoff frequency of the low-pass filter. Below 1 when
+    /// downsampling, so that frequencies the output cannot represent are
+    /// removed rather than folded back.
+    cutoff: f64,
+
+    /// Interleaved input frames which are still needed to compute upcoming
+    /// output frames.
+    history: Vec<f32>,
+
+    /// The position of the next output frame, in frames of `history`.
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(channels: usize, from_rate: u32, to_rate: u32) -> Resampler {
+        let step = from_rate as f64 / to_rate as f64;
+
+        Resampler {
+            channels: channels,
+            step: step,
+            cutoff: if step > 1.0 { 1.0 / step } else { 1.0 },
+            // Start with silence, so that the first frames have something to
+            // be filtered with.
+            history: vec![0.0; HALF_TAPS * channels],
+            position: HALF_TAPS as f64,
+        }
+    }
+
+    /// Whether the resampler actually changes the frequency. When it does not,
+    /// samples are passed through untouched.
+    pub fn is_identity(&self) -> bool {
+        self.step == 1.0
+    }
+
+    /// Feeds interleaved input frames and appends every output frame that can
+    /// be computed from them to `out`.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        if self.is_identity() {
+            out.extend_from_slice(input);
+            return;
+        }
+
+        self.history.extend_from_slice(input);
+        let frames = self.history.len() / self.channels;
+
+        while self.position + (HALF_TAPS as f64) < frames as f64 {
+            let center = self.position.floor() as usize;
+            let first = center + 1 - HALF_TAPS;
+            let last = center + HALF_TAPS;
+
+            for c in 0..self.channels {
+                let mut acc = 0.0;
+                let mut norm = 0.0;
+
+                for i in first..(last + 1) {
+                    let w = self.kernel(i as f64 - self.position);
+                    acc += self.history[i * self.channels + c] as f64 * w;
+                    norm += w;
+                }
+
+                out.push(if norm != 0.0 { (acc / norm) as f32 } else { 0.0 });
+            }
+
+            self.position += self.step;
+        }
+
+        // Forget the frames which will never be needed again.
+        let keep_from = (self.position.floor() as usize + 1).saturating_sub(HALF_TAPS);
+        if keep_from > 0 {
+            self.history.drain(..keep_from * self.channels);
+            self.position -= keep_from as f64;
+        }
+    }
+
+    /// Outputs the frames which were waiting for more input, as if the sound
+    /// was followed by silence.
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        if !self.is_identity() {
+            let padding = vec![0.0; HALF_TAPS * self.channels];
+            self.process(&padding, out);
+        }
+    }
+
+    /// Forgets the previous input, for instance after seeking in a stream.
+    pub fn reset(&mut self) {
+        self.history = vec![0.0; HALF_TAPS * self.channels];
+        self.position = HALF_TAPS as f64;
+    }
+
+    fn kernel(&self, offset: f64) -> f64 {
+        let t = offset / HALF_TAPS as f64;
+        if t <= -1.0 || t >= 1.0 {
+            return 0.0;
+        }
+
+        let x = offset * self.cutoff;
+        let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+        let window = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+
+        sinc * window
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_samples, remix_channels, Resampler, SampleFormat};
+
+    #[test]
+    fn decodes_every_format() {
+        assert_eq!(decode_samples(SampleFormat::U8, &[0, 128, 192]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(decode_samples(SampleFormat::S16, &[0x00, 0x80, 0x00, 0x40]), vec![-1.0, 0.5]);
+        assert_eq!(decode_samples(SampleFormat::S24, &[0x00, 0x00, 0xC0]), vec![-0.5]);
+        assert_eq!(decode_samples(SampleFormat::S32, &[0x00, 0x00, 0x00, 0x40]), vec![0.5]);
+        assert_eq!(decode_samples(SampleFormat::F32, &[0x00, 0x00, 0x00, 0xBF]), vec![-0.5]);
+
+        // Out of range floats are clipped, and an incomplete sample ignored.
+        assert_eq!(decode_samples(SampleFormat::F32, &[0x00, 0x00, 0x00, 0x40, 0x00]), vec![1.0]);
+    }
+
+    #[test]
+    fn remixes_the_channels() {
+        assert_eq!(remix_channels(&[0.25, -0.5], 1, 2), vec![0.25, 0.25, -0.5, -0.5]);
+        assert_eq!(remix_channels(&[0.25, 0.75, -0.5, 0.0], 2, 1), vec![0.5, -0.25]);
+        assert_eq!(remix_channels(&[0.1, 0.2, 0.3, 0.4], 4, 2), vec![0.2, 0.3]);
+    }
+
+    #[test]
+    fn keeps_the_samples_at_the_same_rate() {
+        let input = vec![0.1, -0.2, 0.3, -0.4];
+        let mut out = vec![];
+
+        let mut resampler = Resampler::new(2, 44_100, 44_100);
+        resampler.process(&input, &mut out);
+        resampler.flush(&mut out);
+        assert_eq!(out, input);
+    }
+
+    #[test]
+    fn resamples_a_sine() {
+        // A 441 Hz sine, whose period is 50 frames at 22050 Hz and 100 at
+        // 44100 Hz.
+        let sine = |i: usize, period: f64| (2.0 * ::std::f64::consts::PI * i as f64 / period).sin() as f32;
+        let input: Vec<f32> = (0..2_205).map(|i| sine(i, 50.0)).collect();
+        let mut out = vec![];
+
+        let mut resampler = Resampler::new(1, 22_050, 44_100);
+        resampler.process(&input[..1_000], &mut out);
+        resampler.process(&input[1_000..], &mut out);
+        resampler.flush(&mut out);
+
+        assert!((out.len() as i64 - 4_410).abs() <= 2, "{} frames", out.len());
+
+        // Away from the edges, where silence is filtered in.
+        for i in 100..4_300 {
+            assert!((out[i] - sine(i, 100.0)).abs() < 0.01, "frame {}: {}", i, out[i]);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Sources of samples which are decoded progressively, so that long sounds
+//! never have to be held in memory as a whole.
+
+use super::convert::{self, PcmSpec, Resampler};
+use super::vorbis::{self, VorbisDecoder};
+use super::wav::{self, WavHeader};
+use ::std::fs::File;
+use ::std::io::{BufReader, Read, Seek, SeekFrom};
+
+
+/// The number of frames read at once when decoding a whole file.
+const DECODE_FRAMES: usize = 4_096;
+
+
+/// Decodes a sound file a few frames at a time.
+pub trait Decoder: Send {
+    /// The number of channels and the frequency of the decoded samples.
+    fn format(&self) -> (usize, u32);
+
+    /// Appends at most `frames` interleaved frames to `out`, returning how many
+    /// were actually decoded. Returns 0 once the end of the file is reached.
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, String>;
+
+    /// Moves to the given frame, so that the next call to `read` starts there.
+    fn seek(&mut self, frame: u64) -> Result<(), String>;
+}
+
+
+/// Opens the sound file located at `path` with the appropriate decoder. The
+/// format is detected from the contents of the file, whatever its extension.
+pub fn open(path: &str) -> Result<Box<Decoder>, String> {
+    let file = try!(File::open(path).map_err(|e| format!("Could not open {}: {}", path, e)));
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 12];
+    try!(reader.read_exact(&mut magic)
+        .map_err(|_| format!("{} is too short to be a sound file", path)));
+    try!(reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string()));
+
+    let decoder =
+        if wav::is_wav(&magic) {
+            WavDecoder::new(reader).map(|d| Box::new(d) as Box<Decoder>)
+        } else if vorbis::is_ogg(&magic) {
+            VorbisDecoder::new(reader).map(|d| Box::new(d) as Box<Decoder>)
+        } else {
+            Err("Unrecognized sound format".to_string())
+        };
+
+    decoder.map_err(|e| format!("Could not decode {}: {}", path, e))
+}
+
+
+/// Decodes the whole sound file located at `path`, converting it to
+/// interleaved samples with `channels` channels at `rate` Hz.
+pub fn decode_all(path: &str, channels: usize, rate: u32) -> Result<Vec<f32>, String> {
+    let mut decoder = try!(open(path));
+    let (src_channels, src_rate) = decoder.format();
+    let mut resampler = Resampler::new(channels, src_rate, rate);
+    let mut out = vec![];
+
+    loop {
+        let mut decoded = Vec::with_capacity(DECODE_FRAMES * src_channels);
+        if try!(decoder.read(DECODE_FRAMES, &mut decoded)) == 0 {
+            break;
+        }
+
+        let remixed = convert::remix_channels(&decoded, src_channels, channels);
+        resampler.process(&remixed, &mut out);
+    }
+
+    resampler.flush(&mut out);
+    Ok(out)
+}
+
+
+/// Streams the samples of a WAV file.
+pub struct WavDecoder<R: Read + Seek + Send> {
+    reader: R,
+    header: WavHeader,
+
+    /// The index of the next frame to be read.
+    frame: u64,
+}
+
+impl<R: Read + Seek + Send> WavDecoder<R> {
+    pub fn new(mut reader: R) -> Result<WavDecoder<R>, String> {
+        let header = try!(wav::read_header(&mut reader));
+
+        Ok(WavDecoder {
+            reader: reader,
+            header: header,
+            frame: 0,
+        })
+    }
+
+    pub fn spec(&self) -> PcmSpec {
+        self.header.spec
+    }
+}
+
+impl<R: Read + Seek + Send> Decoder for WavDecoder<R> {
+    fn format(&self) -> (usize, u32) {
+        (self.header.spec.channels, self.header.spec.rate)
+    }
+
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, String> {
+        let left = self.header.frames() - self.frame;
+        let frames = ::std::cmp::min(frames as u64, left) as usize;
+
+        let mut bytes = vec![0u8; frames * self.header.frame_bytes()];
+        try!(self.reader.read_exact(&mut bytes).map_err(|e| e.to_string()));
+
+        out.extend(convert::decode_samples(self.header.spec.format, &bytes));
+        self.frame += frames as u64;
+        Ok(frames)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), String> {
+        let frame = ::std::cmp::min(frame, self.header.frames());
+        let offset = self.header.data_offset + frame * self.header.frame_bytes() as u64;
+
+        try!(self.reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string()));
+        self.frame = frame;
+        Ok(())
+    }
+}
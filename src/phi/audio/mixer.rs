@@ -0,0 +1,460 @@
+use super::music::MusicVoice;
+use ::sdl2::audio::AudioCallback;
+use ::std::collections::HashMap;
+use ::std::sync::Arc;
+
+
+/// The maximum number of sounds which may be played at the same time. Once it
+/// is reached, a new sound interrupts the least important voice.
+const MAX_VOICES: usize = 32;
+
+/// The number of voices which may overlap before the mixer starts lowering
+/// their volume to keep the output from clipping.
+const NORMALIZE_ABOVE: usize = 2;
+
+/// Samples above this level are progressively compressed instead of being
+/// clipped.
+const SOFT_CLIP_KNEE: f32 = 0.8;
+
+/// The number of buses, i.e. of variants of `Bus`.
+const BUSES: usize = 4;
+
+
+// ##############################################################
+// structs
+// ##############################################################
+
+/// The groups of sounds whose volume can be set independently. The volume of
+/// every sound is also affected by the `Master` bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bus {
+    Master = 0,
+    Music = 1,
+    Sfx = 2,
+    Ui = 3,
+}
+
+
+/// Lowers the volume of the music while an important sound is playing, so
+/// that the player does not miss it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ducking {
+    /// How much the music is lowered, in decibels. 0 disables ducking.
+    pub depth_db: f32,
+
+    /// Sounds with at least this priority duck the music.
+    pub min_priority: u8,
+
+    /// The time it takes for the music to be lowered, in seconds.
+    pub attack: f32,
+
+    /// The time it takes for the music to come back once the sounds have
+    /// finished playing, in seconds.
+    pub release: f32,
+}
+
+
+/// A request to start playing a sound, built by `Audio` from a `Sound`.
+pub struct PlayRequest {
+    /// Identifies the sound, so that voices playing it can be counted.
+    pub sound: usize,
+
+    /// Interleaved samples, in the channel layout of the device.
+    pub samples: Arc<Vec<f32>>,
+
+    /// The volume of the voice in the left and right channels.
+    pub gains: (f32, f32),
+
+    pub bus: Bus,
+    pub max_voices: usize,
+    pub priority: u8,
+
+    /// The minimum number of frames between two starts of the sound.
+    pub cooldown: u64,
+}
+
+
+/// A sound which is currently being played by the mixer.
+struct Voice {
+    sound: usize,
+    samples: Arc<Vec<f32>>,
+
+    /// The index of the next sample to be mixed.
+    position: usize,
+
+    /// The volume of the voice in the left and right channels.
+    gains: (f32, f32),
+
+    bus: Bus,
+    priority: u8,
+
+    /// The frame at which the voice started, used to find the oldest one.
+    started: u64,
+}
+
+
+/// Owned by the audio device, the mixer is called from SDL's audio thread
+/// whenever the device needs more data. It sums every active voice into the
+/// output stream and forgets about the voices that have finished playing.
+pub struct Mixer {
+    channels: usize,
+    rate: u32,
+    voices: Vec<Voice>,
+
+    /// The music being played. There is more than one voice only while a
+    /// playlist is crossfading into another one.
+    music: Vec<MusicVoice>,
+
+    /// The number of frames output since the device was opened.
+    clock: u64,
+
+    /// The frame at which each sound was last started.
+    last_started: HashMap<usize, u64>,
+
+    /// The gain applied to every voice so that they do not clip when many of
+    /// them overlap. It follows the number of voices smoothly, to avoid
+    /// sudden jumps in volume.
+    normalization: f32,
+
+    /// The volume and mute state of every bus, indexed by `Bus`.
+    volumes: [f32; BUSES],
+    muted: [bool; BUSES],
+
+    ducking: Ducking,
+
+    /// The gain currently applied to the music because of ducking.
+    duck_gain: f32,
+}
+
+
+// ##############################################################
+// impls
+// ##############################################################
+impl Ducking {
+    /// Never ducks the music.
+    pub fn disabled() -> Ducking {
+        Ducking {
+            depth_db: 0.0,
+            min_priority: 0,
+            attack: 0.0,
+            release: 0.0,
+        }
+    }
+}
+
+
+impl Voice {
+    fn is_finished(&self) -> bool {
+        self.position >= self.samples.len()
+    }
+}
+
+
+impl Mixer {
+    pub fn new(channels: usize, rate: u32) -> Mixer {
+        Mixer {
+            channels: channels,
+            rate: rate,
+            voices: Vec::with_capacity(MAX_VOICES),
+            music: vec![],
+            clock: 0,
+            last_started: HashMap::new(),
+            normalization: 1.0,
+            volumes: [1.0; BUSES],
+            muted: [false; BUSES],
+            ducking: Ducking::disabled(),
+            duck_gain: 1.0,
+        }
+    }
+
+    /// Starts playing a sound from the beginning, without interrupting the
+    /// voices which are already playing unless a limit is reached. Returns
+    /// whether the sound was started: it is ignored if it is still cooling
+    /// down, or if every voice is busy with a more important sound.
+    pub fn play(&mut self, request: PlayRequest) -> bool {
+        if request.max_voices == 0 {
+            return false;
+        }
+
+        if let Some(&last) = self.last_started.get(&request.sound) {
+            if self.clock - last < request.cooldown {
+                return false;
+            }
+        }
+
+        // Restart the oldest voice playing the same sound if there are too many
+        // of them, otherwise steal the least important voice of all.
+        let same_sound: Vec<usize> = (0..self.voices.len())
+            .filter(|&i| self.voices[i].sound == request.sound)
+            .collect();
+
+        if same_sound.len() >= request.max_voices {
+            let oldest = same_sound.into_iter()
+                .min_by_key(|&i| self.voices[i].started)
+                .unwrap();
+            self.voices.remove(oldest);
+        } else if self.voices.len() >= MAX_VOICES {
+            let weakest = (0..self.voices.len())
+                .min_by_key(|&i| (self.voices[i].priority, self.voices[i].started))
+                .unwrap();
+
+            if self.voices[weakest].priority > request.priority {
+                return false;
+            }
+
+            self.voices.remove(weakest);
+        }
+
+        self.last_started.insert(request.sound, self.clock);
+        self.voices.push(Voice {
+            sound: request.sound,
+            samples: request.samples,
+            position: 0,
+            gains: request.gains,
+            bus: request.bus,
+            priority: request.priority,
+            started: self.clock,
+        });
+
+        true
+    }
+
+    /// The number of frames output so far.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// The number of voices which are still being mixed.
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Interrupts every voice.
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    /// Fades in some new music over `fade` frames, while the music which was
+    /// playing until now fades out over the same duration.
+    pub fn play_music(&mut self, mut music: MusicVoice, fade: usize) {
+        self.stop_music(fade);
+        music.fade_to(1.0, fade);
+        self.music.push(music);
+    }
+
+    /// Fades out the music over `fade` frames.
+    pub fn stop_music(&mut self, fade: usize) {
+        for music in &mut self.music {
+            music.fade_to(0.0, fade);
+        }
+    }
+
+    pub fn set_volume(&mut self, bus: Bus, volume: f32) {
+        self.volumes[bus as usize] = volume.max(0.0);
+    }
+
+    pub fn set_muted(&mut self, bus: Bus, muted: bool) {
+        self.muted[bus as usize] = muted;
+    }
+
+    pub fn set_ducking(&mut self, ducking: Ducking) {
+        self.ducking = ducking;
+    }
+
+    /// The volume of the sounds played on `bus`, taking the master bus into
+    /// account.
+    fn bus_gain(&self, bus: Bus) -> f32 {
+        let master = Bus::Master as usize;
+        if self.muted[master] || self.muted[bus as usize] {
+            0.0
+        } else {
+            self.volumes[master] * self.volumes[bus as usize]
+        }
+    }
+
+    /// Moves the ducking gain toward its target, depending on whether an
+    /// important sound is playing, and returns it.
+    fn update_ducking(&mut self, frames: usize) -> f32 {
+        let ducking = self.ducking;
+        let ducked = ducking.depth_db > 0.0 && self.voices.iter()
+            .any(|voice| voice.priority >= ducking.min_priority && voice.bus != Bus::Music);
+
+        let (target, time) =
+            if ducked { (10.0f32.powf(-ducking.depth_db / 20.0), ducking.attack) }
+            else { (1.0, ducking.release) };
+
+        let progress =
+            if time <= 0.0 { 1.0 }
+            else { (frames as f32 / (time * self.rate as f32)).min(1.0) };
+
+        self.duck_gain += (target - self.duck_gain) * progress;
+        self.duck_gain
+    }
+}
+
+impl AudioCallback for Mixer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for dst in out.iter_mut() {
+            *dst = 0.0;
+        }
+
+        let channels = self.channels;
+        let frames = out.len() / channels;
+
+        // The more voices overlap, the quieter each of them gets. The gain is
+        // ramped over the whole buffer so that voices starting or ending do not
+        // cause a click.
+        let overlap = ::std::cmp::max(self.voices.len(), NORMALIZE_ABOVE);
+        let from = self.normalization;
+        let to = (NORMALIZE_ABOVE as f32 / overlap as f32).sqrt();
+
+        let bus_gains = [
+            self.bus_gain(Bus::Master),
+            self.bus_gain(Bus::Music),
+            self.bus_gain(Bus::Sfx),
+            self.bus_gain(Bus::Ui),
+        ];
+
+        let music_from = self.duck_gain * bus_gains[Bus::Music as usize];
+        let music_to = self.update_ducking(frames) * bus_gains[Bus::Music as usize];
+
+        for voice in &mut self.voices {
+            // Only read what is left of the buffer, so that a voice ending in
+            // the middle of a callback is padded with silence instead of
+            // reading past its end.
+            let remaining = &voice.samples[voice.position..];
+            let mixed = ::std::cmp::min(remaining.len(), out.len());
+
+            // Voices always start on a frame boundary, so the index of a sample
+            // tells which channel it belongs to.
+            let bus_gain = bus_gains[voice.bus as usize];
+            let (left, right) = (voice.gains.0 * bus_gain, voice.gains.1 * bus_gain);
+            for (i, (dst, src)) in out.iter_mut().zip(remaining.iter()).enumerate() {
+                let normalization = from + (to - from) * (i / channels) as f32 / frames as f32;
+
+                *dst += *src * normalization *
+                    if channels == 1 { (left + right) / 2.0 }
+                    else if i % channels % 2 == 0 { left }
+                    else { right };
+            }
+
+            voice.position += mixed;
+        }
+
+        self.normalization = to;
+        self.voices.retain(|voice| !voice.is_finished());
+
+        for music in &mut self.music {
+            music.mix(out, channels, music_from, music_to);
+        }
+
+        // Dropping a voice also stops the thread which was streaming it.
+        self.music.retain(|music| !music.is_finished());
+
+        // Summing voices may go out of the range accepted by the device.
+        for dst in out.iter_mut() {
+            *dst = soft_clip(*dst);
+        }
+
+        self.clock += frames as u64;
+    }
+}
+
+
+/// Leaves quiet samples untouched, and smoothly compresses the loud ones so
+/// that they never exceed 1.
+fn soft_clip(sample: f32) -> f32 {
+    let level = sample.abs();
+    if level <= SOFT_CLIP_KNEE {
+        return sample;
+    }
+
+    let headroom = 1.0 - SOFT_CLIP_KNEE;
+    let compressed = SOFT_CLIP_KNEE + headroom * ((level - SOFT_CLIP_KNEE) / headroom).tanh();
+    compressed * sample.signum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Bus, Mixer, PlayRequest, MAX_VOICES};
+    use ::sdl2::audio::AudioCallback;
+    use ::std::sync::Arc;
+
+    /// A second of silence, which is long enough not to end during a test.
+    fn request(sound: usize, priority: u8, max_voices: usize, cooldown: u64) -> PlayRequest {
+        PlayRequest {
+            sound: sound,
+            samples: Arc::new(vec![0.0; 44_100]),
+            gains: (1.0, 1.0),
+            bus: Bus::Sfx,
+            max_voices: max_voices,
+            priority: priority,
+            cooldown: cooldown,
+        }
+    }
+
+    /// Lets `frames` frames of a mono output pass.
+    fn advance(mixer: &mut Mixer, frames: usize) {
+        mixer.callback(&mut vec![0.0; frames]);
+    }
+
+    fn sounds(mixer: &Mixer) -> Vec<usize> {
+        mixer.voices.iter().map(|voice| voice.sound).collect()
+    }
+
+    #[test]
+    fn steals_the_least_important_voice() {
+        let mut mixer = Mixer::new(1, 44_100);
+        for sound in 0..MAX_VOICES {
+            let priority = if sound == 5 || sound == 7 { 0 } else { 1 };
+            assert!(mixer.play(request(sound, priority, 4, 0)));
+            advance(&mut mixer, 10);
+        }
+
+        // Among the least important voices, the oldest one goes first.
+        assert!(mixer.play(request(100, 1, 4, 0)));
+        assert_eq!(mixer.active_voices(), MAX_VOICES);
+        assert!(!sounds(&mixer).contains(&5));
+        assert!(sounds(&mixer).contains(&7));
+
+        assert!(mixer.play(request(101, 2, 4, 0)));
+        assert!(!sounds(&mixer).contains(&7));
+
+        // Every voice is more important than this one.
+        assert!(!mixer.play(request(102, 0, 4, 0)));
+        assert!(!sounds(&mixer).contains(&102));
+    }
+
+    #[test]
+    fn restarts_the_oldest_voice_of_a_sound() {
+        let mut mixer = Mixer::new(1, 44_100);
+        for _ in 0..3 {
+            assert!(mixer.play(request(1, 1, 2, 0)));
+            advance(&mut mixer, 10);
+        }
+        assert!(mixer.play(request(2, 1, 2, 0)));
+
+        let started: Vec<u64> = mixer.voices.iter().map(|voice| voice.started).collect();
+        assert_eq!(sounds(&mixer), vec![1, 1, 2]);
+        assert_eq!(started, vec![10, 20, 30]);
+
+        assert!(!mixer.play(request(3, 1, 0, 0)));
+    }
+
+    #[test]
+    fn ignores_a_sound_cooling_down() {
+        let mut mixer = Mixer::new(1, 44_100);
+        assert!(mixer.play(request(1, 1, 4, 100)));
+
+        advance(&mut mixer, 99);
+        assert!(!mixer.play(request(1, 1, 4, 100)));
+        assert!(mixer.play(request(2, 1, 4, 100)));
+
+        advance(&mut mixer, 1);
+        assert!(mixer.play(request(1, 1, 4, 100)));
+        assert_eq!(sounds(&mixer), vec![1, 2, 1]);
+    }
+}
@@ -0,0 +1,430 @@
+pub mod bank;
+pub mod convert;
+pub mod decoder;
+pub mod mixer;
+pub mod music;
+pub mod synth;
+pub mod vorbis;
+pub mod wav;
+
+use self::bank::Sound;
+use self::mixer::{Mixer, PlayRequest};
+pub use self::mixer::{Bus, Ducking};
+use self::music::Playlist;
+use self::wav::WavWriter;
+use ::sdl2::Sdl;
+use ::sdl2::audio::{AudioCallback, AudioDevice, AudioFormat, AudioSpec, AudioSpecDesired};
+
+
+/// The format in which the device is opened. Every sound is converted to it
+/// by the `SoundBank`, so that the mixer never has to do it while playing.
+const OUTPUT_FREQ: i32 = 44_100;
+const OUTPUT_CHANNELS: u8 = 2;
+const OUTPUT_SAMPLES: u16 = 1_024;
+
+/// The default duration of a crossfade between two playlists, in seconds.
+const DEFAULT_CROSSFADE: f64 = 1.5;
+
+/// The default distance under which positional sounds play at full volume.
+const DEFAULT_REFERENCE_DISTANCE: f64 = 200.0;
+
+/// By default, sounds with a priority of at least 5 lower the music by 6dB.
+const DEFAULT_DUCKING: Ducking = Ducking {
+    depth_db: 6.0,
+    min_priority: 5,
+    attack: 0.05,
+    release: 0.4,
+};
+
+
+/// Where the mixed sounds go.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioOutput {
+    /// The default playback device.
+    Device,
+
+    /// Nowhere. The mixer only runs when `Audio::advance` is called, so that
+    /// the sounds played by the game can be checked without a sound card.
+    Null,
+
+    /// A WAV file, which grows every time `Audio::advance` is called.
+    File(String),
+}
+
+
+/// Records that the mixer started a voice, when running offline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceStart {
+    /// The id of the sound being played.
+    pub sound: usize,
+
+    /// The number of times `Audio::advance` had been called before.
+    pub step: u64,
+
+    /// The frame of the output at which the voice started.
+    pub frame: u64,
+}
+
+
+/// Describes how positional sounds are heard.
+#[derive(Clone, Copy, Debug)]
+pub struct Spatial {
+    /// The width of the play field. Sounds are panned from the left speaker
+    /// at `x = 0` to the right one at `x = width`.
+    pub width: f64,
+
+    /// Where the sounds are heard from, usually the player. If `None`, sounds
+    /// are not attenuated with distance.
+    pub listener: Option<(f64, f64)>,
+
+    /// The distance from the listener under which a sound plays at full
+    /// volume. It is heard at half volume twice as far, and so on.
+    pub reference_distance: f64,
+}
+
+
+impl Spatial {
+    /// The volume, in the left and right channels, of a sound emitted at
+    /// `position`.
+    pub fn gains(&self, position: (f64, f64)) -> (f32, f32) {
+        // From -1 on the far left to 1 on the far right.
+        let pan = (position.0 / self.width * 2.0 - 1.0).max(-1.0).min(1.0);
+
+        let attenuation = match self.listener {
+            None => 1.0,
+            Some((x, y)) => {
+                let (dx, dy) = (position.0 - x, position.1 - y);
+                let distance = (dx * dx + dy * dy).sqrt();
+                if distance <= self.reference_distance { 1.0 }
+                else { self.reference_distance / distance }
+            },
+        };
+
+        // The centered sound plays at full volume on both sides, and moving it
+        // to one side progressively silences the other one.
+        let left = (1.0 - pan).min(1.0) * attenuation;
+        let right = (1.0 + pan).min(1.0) * attenuation;
+        (left as f32, right as f32)
+    }
+}
+
+
+/// Owns the mixer: either SDL calls it whenever the device needs more data,
+/// or the game does so itself through `Audio::advance`.
+enum Output {
+    Device(AudioDevice<Mixer>),
+
+    Offline {
+        mixer: Mixer,
+        writer: Option<WavWriter>,
+
+        /// The fraction of a frame which was not rendered yet, so that the
+        /// output does not drift when a step does not last a whole number of
+        /// frames.
+        pending: f64,
+    },
+}
+
+
+/// Keeps the playback device open for the whole lifetime of the game, so that
+/// playing a sound is only a matter of handing its samples to the mixer.
+pub struct Audio {
+    output: Output,
+    spec: AudioSpec,
+
+    /// The number of times `advance` was called.
+    steps: u64,
+
+    /// Every voice started so far, when running offline.
+    voice_log: Vec<VoiceStart>,
+
+    /// The name of the playlist currently playing, if any.
+    playlist: Option<&'static str>,
+
+    /// The time it takes to go from a playlist to the next, in seconds.
+    pub crossfade: f64,
+
+    /// The settings used by `play_at`.
+    pub spatial: Spatial,
+
+    /// The settings of the buses, as last given to the mixer. They are kept
+    /// here so that they can be read without locking the device.
+    volumes: [f32; 4],
+    muted: [bool; 4],
+    ducking: Ducking,
+}
+
+impl Audio {
+    /// Starts a mixer sending its output to `output`.
+    pub fn open(sdl: &Sdl, output: &AudioOutput) -> Result<Audio, String> {
+        match *output {
+            AudioOutput::Device => Audio::new(sdl),
+            AudioOutput::Null => Audio::offline(None),
+            AudioOutput::File(ref path) => Audio::offline(Some(path)),
+        }
+    }
+
+    /// Opens the default playback device and starts the mixer, which outputs
+    /// silence until a sound is played.
+    pub fn new(sdl: &Sdl) -> Result<Audio, String> {
+        let subsystem = try!(sdl.audio().map_err(|e| e.to_string()));
+        let desired = AudioSpecDesired {
+            freq: Some(OUTPUT_FREQ),
+            channels: Some(OUTPUT_CHANNELS),
+            samples: Some(OUTPUT_SAMPLES),
+        };
+
+        let mut obtained = None;
+        let mut device = try!(subsystem.open_playback(None, desired, |spec| {
+            obtained = Some(spec);
+            Mixer::new(spec.channels as usize, spec.freq as u32)
+        }).map_err(|e| e.to_string()));
+
+        device.lock().set_ducking(DEFAULT_DUCKING);
+        device.resume();
+
+        Ok(Audio::with_output(Output::Device(device), obtained.unwrap()))
+    }
+
+    /// Starts a mixer which does not need any sound card, and only runs when
+    /// `advance` is called. If `path` is given, its output is written there.
+    pub fn offline(path: Option<&str>) -> Result<Audio, String> {
+        let spec = AudioSpec {
+            freq: OUTPUT_FREQ,
+            format: AudioFormat::f32_sys(),
+            channels: OUTPUT_CHANNELS,
+            silence: 0,
+            samples: OUTPUT_SAMPLES,
+            size: OUTPUT_SAMPLES as u32 * OUTPUT_CHANNELS as u32 * 4,
+        };
+
+        let writer = match path {
+            Some(path) => Some(try!(WavWriter::create(path, spec.channels as usize, spec.freq as u32))),
+            None => None,
+        };
+
+        let mut mixer = Mixer::new(spec.channels as usize, spec.freq as u32);
+        mixer.set_ducking(DEFAULT_DUCKING);
+
+        Ok(Audio::with_output(Output::Offline {
+            mixer: mixer,
+            writer: writer,
+            pending: 0.0,
+        }, spec))
+    }
+
+    fn with_output(output: Output, spec: AudioSpec) -> Audio {
+        Audio {
+            output: output,
+            spec: spec,
+            steps: 0,
+            voice_log: vec![],
+            playlist: None,
+            crossfade: DEFAULT_CROSSFADE,
+            spatial: Spatial {
+                width: 1.0,
+                listener: None,
+                reference_distance: DEFAULT_REFERENCE_DISTANCE,
+            },
+            volumes: [1.0; 4],
+            muted: [false; 4],
+            ducking: DEFAULT_DUCKING,
+        }
+    }
+
+    /// The format in which the device was opened.
+    pub fn spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    /// Whether the mixer runs only when `advance` is called.
+    pub fn is_offline(&self) -> bool {
+        match self.output {
+            Output::Device(_) => false,
+            Output::Offline { .. } => true,
+        }
+    }
+
+    /// Tells the mixer that `elapsed` seconds have passed. When running
+    /// offline, this renders the corresponding frames, and writes them to the
+    /// output file if there is one. The device runs on its own otherwise.
+    pub fn advance(&mut self, elapsed: f64) -> Result<(), String> {
+        self.steps += 1;
+        let channels = self.spec.channels as usize;
+        let rate = self.spec.freq as f64;
+
+        if let Output::Offline { ref mut mixer, ref mut writer, ref mut pending } = self.output {
+            let wanted = *pending + elapsed * rate;
+            let mut frames = wanted.floor() as usize;
+            *pending = wanted - frames as f64;
+
+            let mut buffer = vec![0.0; OUTPUT_SAMPLES as usize * channels];
+            while frames > 0 {
+                let block = ::std::cmp::min(frames, OUTPUT_SAMPLES as usize);
+                mixer.callback(&mut buffer[..block * channels]);
+                if let Some(ref mut writer) = *writer {
+                    try!(writer.write(&buffer[..block * channels]));
+                }
+                frames -= block;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every voice started so far, in order. Always empty unless running
+    /// offline, since the device plays voices at its own pace.
+    pub fn voice_log(&self) -> &[VoiceStart] {
+        &self.voice_log
+    }
+
+    /// Finishes writing the output file, if any. This also happens when the
+    /// `Audio` is dropped, but errors are then only printed.
+    pub fn finish(&mut self) -> Result<(), String> {
+        match self.output {
+            Output::Offline { writer: Some(ref mut writer), .. } => writer.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the mixer.
+    fn with_mixer<F, T>(&mut self, f: F) -> T where F: FnOnce(&mut Mixer) -> T {
+        match self.output {
+            Output::Device(ref mut device) => f(&mut *device.lock()),
+            Output::Offline { ref mut mixer, .. } => f(mixer),
+        }
+    }
+
+    /// Starts playing a sound and returns immediately. The sound is mixed
+    /// with every other sound currently playing, within the limits given by
+    /// its settings. Returns whether the sound was actually started.
+    pub fn play(&mut self, sound: &Sound) -> bool {
+        self.play_with_gains(sound, (1.0, 1.0))
+    }
+
+    /// Plays a sound emitted at `position`: it is panned depending on its
+    /// horizontal position, and attenuated by its distance to the listener.
+    pub fn play_at(&mut self, sound: &Sound, position: (f64, f64)) -> bool {
+        let gains = self.spatial.gains(position);
+        self.play_with_gains(sound, gains)
+    }
+
+    fn play_with_gains(&mut self, sound: &Sound, gains: (f32, f32)) -> bool {
+        let request = PlayRequest {
+            sound: sound.id(),
+            samples: sound.samples(),
+            gains: gains,
+            bus: sound.settings.bus,
+            max_voices: sound.settings.max_voices,
+            priority: sound.settings.priority,
+            cooldown: (sound.settings.cooldown * self.spec.freq as f64) as u64,
+        };
+
+        let step = self.steps;
+        let offline = self.is_offline();
+        let (started, frame) = self.with_mixer(|mixer| (mixer.play(request), mixer.clock()));
+
+        if started && offline {
+            self.voice_log.push(VoiceStart {
+                sound: sound.id(),
+                step: step,
+                frame: frame,
+            });
+        }
+
+        started
+    }
+
+    /// The number of sounds currently being played.
+    pub fn active_voices(&mut self) -> usize {
+        self.with_mixer(|mixer| mixer.active_voices())
+    }
+
+    /// Interrupts every sound currently being played.
+    pub fn stop_all(&mut self) {
+        self.with_mixer(|mixer| mixer.stop_all());
+    }
+
+    /// Crossfades to the given playlist, or fades out the music if `None` is
+    /// given. Nothing happens if the playlist is the one already playing.
+    pub fn switch_music(&mut self, playlist: Option<Playlist>) {
+        if self.playlist == playlist.as_ref().map(|p| p.name) {
+            return;
+        }
+
+        let fade = (self.crossfade * self.spec.freq as f64) as usize;
+        self.playlist = playlist.as_ref().map(|p| p.name);
+
+        match playlist {
+            Some(playlist) => {
+                let mut voice = playlist.stream(self.spec.channels as usize, self.spec.freq as u32);
+                voice.set_blocking(self.is_offline());
+                self.with_mixer(|mixer| mixer.play_music(voice, fade));
+            },
+            None =>
+                self.with_mixer(|mixer| mixer.stop_music(fade)),
+        }
+    }
+
+    /// The volume of `bus`, between 0 and 1 unless the sounds are amplified.
+    pub fn volume(&self, bus: Bus) -> f32 {
+        self.volumes[bus as usize]
+    }
+
+    /// Changes the volume of every sound played on `bus`, including the ones
+    /// which are already playing.
+    pub fn set_volume(&mut self, bus: Bus, volume: f32) {
+        self.volumes[bus as usize] = volume.max(0.0);
+        self.with_mixer(|mixer| mixer.set_volume(bus, volume));
+    }
+
+    pub fn is_muted(&self, bus: Bus) -> bool {
+        self.muted[bus as usize]
+    }
+
+    /// Silences `bus` without losing its volume.
+    pub fn set_muted(&mut self, bus: Bus, muted: bool) {
+        self.muted[bus as usize] = muted;
+        self.with_mixer(|mixer| mixer.set_muted(bus, muted));
+    }
+
+    pub fn ducking(&self) -> Ducking {
+        self.ducking
+    }
+
+    /// Changes how much, and for which sounds, the music is lowered.
+    pub fn set_ducking(&mut self, ducking: Ducking) {
+        self.ducking = ducking;
+        self.with_mixer(|mixer| mixer.set_ducking(ducking));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Audio, VoiceStart};
+    use ::phi::audio::bank::SoundBank;
+    use ::phi::audio::synth::SfxParams;
+
+    #[test]
+    fn logs_the_voices_and_writes_every_frame() {
+        let path = ::std::env::temp_dir().join("arcade-rs-offline.wav");
+        let path = path.to_str().unwrap();
+
+        let mut audio = Audio::offline(Some(path)).unwrap();
+        let mut bank = SoundBank::new(audio.spec());
+        let sound = bank.synthesize("laser", &SfxParams::laser(1));
+
+        audio.advance(0.1).unwrap();
+        audio.advance(0.1).unwrap();
+        assert!(audio.play(&sound));
+        audio.advance(0.5).unwrap();
+        audio.finish().unwrap();
+
+        assert_eq!(audio.voice_log(), &[VoiceStart { sound: sound.id(), step: 2, frame: 8_820 }]);
+
+        // 0.7 seconds of stereo floats, after the 44 bytes of the header.
+        let len = ::std::fs::metadata(path).unwrap().len();
+        assert_eq!(len, 44 + 30_870 * 2 * 4);
+    }
+}
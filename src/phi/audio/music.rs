@@ -0,0 +1,286 @@
+//! Background music, streamed from the disk by a separate thread and handed
+//! to the mixer in small chunks.
+
+use super::convert::{self, Resampler};
+use super::decoder::{self, Decoder};
+use ::std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use ::std::thread;
+
+
+/// The number of frames decoded at once by the streaming thread.
+const CHUNK_FRAMES: usize = 4_096;
+
+/// The number of chunks decoded in advance. This must be enough to cover a
+/// few frames of the game, so that the music keeps playing during a stall.
+const CHUNKS_AHEAD: usize = 4;
+
+
+// ##############################################################
+// structs
+// ##############################################################
+
+/// A single music file, optionally looping between two points.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track {
+    pub path: String,
+
+    /// Where to restart playing once the end of the loop is reached, in
+    /// seconds. `None` if the track should only be played once.
+    pub loop_start: Option<f64>,
+
+    /// Where the loop ends, in seconds. `None` to loop at the end of the file.
+    pub loop_end: Option<f64>,
+}
+
+
+/// The tracks played while some view is shown. Tracks are played in order,
+/// and the playlist starts over once the last one has finished. A looping
+/// track is played until another playlist replaces this one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playlist {
+    /// Identifies the playlist, so that changing to a view which uses the same
+    /// music does not restart it.
+    pub name: &'static str,
+    pub tracks: Vec<Track>,
+}
+
+
+/// The mixer's side of a stream: it receives the chunks decoded by the
+/// streaming thread and fades them in or out.
+pub struct MusicVoice {
+    chunks: Receiver<Vec<f32>>,
+    chunk: Vec<f32>,
+    position: usize,
+
+    gain: f32,
+    target: f32,
+
+    /// How much `gain` moves toward `target` at every frame.
+    fade_step: f32,
+
+    /// Set once the streaming thread has stopped and every chunk was played.
+    exhausted: bool,
+
+    /// Whether to wait for the streaming thread instead of playing silence
+    /// when it is late, so that rendering offline gives the same result
+    /// whatever the speed of the machine.
+    blocking: bool,
+}
+
+
+// ##############################################################
+// impls
+// ##############################################################
+impl Track {
+    /// A track which is played once.
+    pub fn new(path: &str) -> Track {
+        Track {
+            path: path.to_string(),
+            loop_start: None,
+            loop_end: None,
+        }
+    }
+
+    /// Loops over the whole file.
+    pub fn looping(self) -> Track {
+        self.loop_points(0.0, None)
+    }
+
+    /// Plays from the beginning of the file, then loops between `start` and
+    /// `end` (or the end of the file), both in seconds. This allows a track to
+    /// have an intro which is not repeated.
+    pub fn loop_points(self, start: f64, end: Option<f64>) -> Track {
+        Track {
+            loop_start: Some(start),
+            loop_end: end,
+            ..self
+        }
+    }
+}
+
+
+impl Playlist {
+    pub fn new(name: &'static str, tracks: Vec<Track>) -> Playlist {
+        Playlist {
+            name: name,
+            tracks: tracks,
+        }
+    }
+
+    /// Starts decoding the playlist on a new thread. The samples are converted
+    /// to `channels` channels at `rate` Hz, and are sent through the returned
+    /// voice, which must be given to the mixer. The thread stops on its own
+    /// once the voice is dropped.
+    pub fn stream(&self, channels: usize, rate: u32) -> MusicVoice {
+        let (tx, rx) = mpsc::sync_channel(CHUNKS_AHEAD);
+        let tracks = self.tracks.clone();
+
+        thread::spawn(move || {
+            if tracks.is_empty() {
+                return;
+            }
+
+            // A track which cannot be read is skipped, unless none of them
+            // can, in which case the playlist stays silent.
+            let mut failures = 0;
+
+            for track in tracks.iter().cycle() {
+                match stream_track(track, channels, rate, &tx) {
+                    Ok(true) => failures = 0,
+                    // The voice was dropped, so nobody is listening anymore.
+                    Ok(false) => return,
+                    Err(e) => {
+                        println!("Could not stream {}: {}", track.path, e);
+                        failures += 1;
+                        if failures >= tracks.len() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        MusicVoice::new(rx)
+    }
+}
+
+
+impl MusicVoice {
+    fn new(chunks: Receiver<Vec<f32>>) -> MusicVoice {
+        MusicVoice {
+            chunks: chunks,
+            chunk: vec![],
+            position: 0,
+            gain: 0.0,
+            target: 0.0,
+            fade_step: 1.0,
+            exhausted: false,
+            blocking: false,
+        }
+    }
+
+    /// Moves the gain of the voice to `target` over `frames` frames.
+    pub fn fade_to(&mut self, target: f32, frames: usize) {
+        self.target = target;
+        self.fade_step =
+            if frames == 0 { 1.0 }
+            else { (target - self.gain).abs() / frames as f32 };
+
+        if frames == 0 {
+            self.gain = target;
+        }
+    }
+
+    pub fn set_blocking(&mut self, blocking: bool) {
+        self.blocking = blocking;
+    }
+
+    /// Whether the voice has nothing left to play: either it reached the end
+    /// of its playlist, or it was completely faded out.
+    pub fn is_finished(&self) -> bool {
+        self.exhausted || (self.target == 0.0 && self.gain == 0.0)
+    }
+
+    /// Adds the next frames of the stream to `out`, which holds interleaved
+    /// frames of `channels` samples. On top of the voice's own fade, the volume
+    /// is ramped from `from` to `to` over the length of `out`. If the streaming
+    /// thread is late, the voice is silent until the next chunk arrives, unless
+    /// it is blocking.
+    pub fn mix(&mut self, out: &mut [f32], channels: usize, from: f32, to: f32) {
+        let frames = out.len() / channels;
+        let mut i = 0;
+
+        while i + channels <= out.len() {
+            if self.position >= self.chunk.len() {
+                let next =
+                    if self.blocking { self.chunks.recv().map_err(|_| TryRecvError::Disconnected) }
+                    else { self.chunks.try_recv() };
+
+                match next {
+                    Ok(chunk) => {
+                        self.chunk = chunk;
+                        self.position = 0;
+                        continue;
+                    },
+                    Err(TryRecvError::Empty) => return,
+                    Err(TryRecvError::Disconnected) => {
+                        self.exhausted = true;
+                        return;
+                    },
+                }
+            }
+
+            let volume = from + (to - from) * (i / channels) as f32 / frames as f32;
+            for c in 0..channels {
+                out[i + c] += self.chunk[self.position + c] * self.gain * volume;
+            }
+
+            if self.gain < self.target {
+                self.gain = (self.gain + self.fade_step).min(self.target);
+            } else if self.gain > self.target {
+                self.gain = (self.gain - self.fade_step).max(self.target);
+            }
+
+            i += channels;
+            self.position += channels;
+        }
+    }
+}
+
+
+/// Decodes a whole track, sending it through `tx` chunk by chunk. Returns
+/// `Ok(false)` as soon as the receiving end is dropped.
+fn stream_track(track: &Track, channels: usize, rate: u32, tx: &SyncSender<Vec<f32>>)
+        -> Result<bool, String> {
+    let mut decoder = try!(decoder::open(&track.path));
+    let (src_channels, src_rate) = decoder.format();
+    let to_frame = |seconds: f64| (seconds * src_rate as f64) as u64;
+
+    let loop_start = track.loop_start.map(&to_frame);
+    let loop_end = track.loop_end.map(&to_frame);
+
+    // The resampler is shared by every iteration of the loop, so that there
+    // is no discontinuity at the loop point.
+    let mut resampler = Resampler::new(channels, src_rate, rate);
+    let mut frame = 0u64;
+    let mut empty_reads = 0;
+
+    loop {
+        let wanted = match loop_end {
+            Some(end) if loop_start.is_some() =>
+                ::std::cmp::min(CHUNK_FRAMES as u64, end.saturating_sub(frame)) as usize,
+            _ => CHUNK_FRAMES,
+        };
+
+        let mut decoded = Vec::with_capacity(wanted * src_channels);
+        let read = try!(decoder.read(wanted, &mut decoded));
+        frame += read as u64;
+
+        // Looping over nothing would spin forever.
+        empty_reads = if read == 0 { empty_reads + 1 } else { 0 };
+        if empty_reads > 1 {
+            return Ok(true);
+        }
+
+        let mut chunk = Vec::with_capacity(CHUNK_FRAMES * channels);
+        let remixed = convert::remix_channels(&decoded, src_channels, channels);
+        resampler.process(&remixed, &mut chunk);
+
+        if read < wanted || read == 0 {
+            match loop_start {
+                Some(start) => {
+                    try!(decoder.seek(start));
+                    frame = start;
+                },
+                None => {
+                    resampler.flush(&mut chunk);
+                    return Ok(tx.send(chunk).is_ok());
+                },
+            }
+        }
+
+        if !chunk.is_empty() && tx.send(chunk).is_err() {
+            return Ok(false);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! A small synthesizer in the spirit of sfxr, which renders retro sound
+//! effects from a handful of parameters instead of loading them from a file.
+
+use ::phi::random::seeded;
+use ::rand::Rng;
+use ::std::f64::consts::PI;
+
+
+/// The number of random values making up one period of noise.
+const NOISE_SAMPLES: usize = 32;
+
+
+// ##############################################################
+// structs
+// ##############################################################
+
+/// The shape of the oscillator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+
+/// Everything needed to render a sound effect. Times are in seconds and
+/// frequencies in Hz. Rendering the same parameters always produces the same
+/// samples, since the noise is drawn from a generator initialized with `seed`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SfxParams {
+    pub waveform: Waveform,
+
+    /// The time it takes to reach full volume.
+    pub attack: f64,
+    /// The time spent at full volume.
+    pub sustain: f64,
+    /// How much louder the start of the sustain is, fading back to full
+    /// volume by its end. 0 for no punch.
+    pub punch: f64,
+    /// The time it takes to go from full volume to silence.
+    pub decay: f64,
+
+    /// The frequency at which the sound starts.
+    pub frequency: f64,
+    /// The sound stops early if its frequency falls below this one.
+    pub min_frequency: f64,
+    /// How fast the frequency changes, in octaves per second.
+    pub slide: f64,
+    /// How fast `slide` itself changes, in octaves per second squared.
+    pub delta_slide: f64,
+
+    /// How much the frequency oscillates, as a fraction of itself.
+    pub vibrato_depth: f64,
+    /// How many times the frequency oscillates every second.
+    pub vibrato_speed: f64,
+
+    /// After `arpeggio_time` seconds, the frequency is multiplied by
+    /// `arpeggio`. A value of 1 disables it.
+    pub arpeggio: f64,
+    pub arpeggio_time: f64,
+
+    /// The fraction of a period during which a square wave is high, and how
+    /// fast it changes every second.
+    pub duty: f64,
+    pub duty_sweep: f64,
+
+    pub volume: f64,
+    pub seed: u64,
+}
+
+
+// ##############################################################
+// impls
+// ##############################################################
+impl SfxParams {
+    /// A plain square beep, to be tweaked field by field.
+    pub fn new(seed: u64) -> SfxParams {
+        SfxParams {
+            waveform: Waveform::Square,
+            attack: 0.0,
+            sustain: 0.1,
+            punch: 0.0,
+            decay: 0.2,
+            frequency: 440.0,
+            min_frequency: 0.0,
+            slide: 0.0,
+            delta_slide: 0.0,
+            vibrato_depth: 0.0,
+            vibrato_speed: 0.0,
+            arpeggio: 1.0,
+            arpeggio_time: 0.0,
+            duty: 0.5,
+            duty_sweep: 0.0,
+            volume: 0.5,
+            seed: seed,
+        }
+    }
+
+    /// A falling zap, as fired by a laser cannon.
+    pub fn laser(seed: u64) -> SfxParams {
+        let mut rng = seeded(seed);
+        let waveforms = [Waveform::Square, Waveform::Sawtooth, Waveform::Sine];
+
+        SfxParams {
+            waveform: waveforms[rng.gen_range(0, waveforms.len())],
+            sustain: rng.gen_range(0.04, 0.12),
+            decay: rng.gen_range(0.08, 0.25),
+            frequency: rng.gen_range(600.0, 1_500.0),
+            min_frequency: 100.0,
+            slide: -rng.gen_range(4.0, 10.0),
+            duty: rng.gen_range(0.2, 0.5),
+            duty_sweep: rng.gen_range(-0.5, 0.5),
+            volume: 0.3,
+            ..SfxParams::new(seed)
+        }
+    }
+
+    /// A bright two-note jingle, as played when picking up an item.
+    pub fn pickup(seed: u64) -> SfxParams {
+        let mut rng = seeded(seed);
+
+        SfxParams {
+            sustain: rng.gen_range(0.04, 0.1),
+            punch: rng.gen_range(0.3, 0.6),
+            decay: rng.gen_range(0.1, 0.3),
+            frequency: rng.gen_range(700.0, 1_200.0),
+            arpeggio: rng.gen_range(1.2, 1.6),
+            arpeggio_time: rng.gen_range(0.04, 0.1),
+            volume: 0.4,
+            ..SfxParams::new(seed)
+        }
+    }
+
+    /// A short, dull thud.
+    pub fn hit(seed: u64) -> SfxParams {
+        let mut rng = seeded(seed);
+
+        SfxParams {
+            waveform: if rng.gen() { Waveform::Noise } else { Waveform::Square },
+            sustain: rng.gen_range(0.02, 0.06),
+            decay: rng.gen_range(0.08, 0.2),
+            frequency: rng.gen_range(200.0, 500.0),
+            slide: -rng.gen_range(2.0, 6.0),
+            volume: 0.5,
+            ..SfxParams::new(seed)
+        }
+    }
+
+    /// A rumbling burst of noise.
+    pub fn explosion(seed: u64) -> SfxParams {
+        let mut rng = seeded(seed);
+
+        SfxParams {
+            waveform: Waveform::Noise,
+            sustain: rng.gen_range(0.1, 0.3),
+            punch: rng.gen_range(0.2, 0.6),
+            decay: rng.gen_range(0.3, 0.6),
+            frequency: rng.gen_range(80.0, 250.0),
+            slide: -rng.gen_range(0.0, 1.0),
+            vibrato_depth: if rng.gen() { rng.gen_range(0.0, 0.3) } else { 0.0 },
+            vibrato_speed: rng.gen_range(5.0, 20.0),
+            volume: 0.6,
+            ..SfxParams::new(seed)
+        }
+    }
+
+    /// The length of the rendered sound, in seconds, unless it stops early
+    /// because of `min_frequency`.
+    pub fn duration(&self) -> f64 {
+        self.attack + self.sustain + self.decay
+    }
+
+    /// Renders the sound as interleaved samples with `channels` identical
+    /// channels at `rate` Hz, ready to be played by the mixer.
+    pub fn render(&self, channels: usize, rate: u32) -> Vec<f32> {
+        let mut rng = seeded(self.seed);
+        let dt = 1.0 / rate as f64;
+        let frames = (self.duration() * rate as f64) as usize;
+
+        let mut out = Vec::with_capacity(frames * channels);
+        let mut noise = [0.0f64; NOISE_SAMPLES];
+        for n in noise.iter_mut() {
+            *n = rng.gen_range(-1.0, 1.0);
+        }
+
+        let mut phase = 0.0;
+        let mut frequency = self.frequency;
+        let mut slide = self.slide;
+        let mut arpeggio_done = false;
+
+        for frame in 0..frames {
+            let t = frame as f64 * dt;
+
+            if !arpeggio_done && self.arpeggio != 1.0 && t >= self.arpeggio_time {
+                frequency *= self.arpeggio;
+                arpeggio_done = true;
+            }
+
+            slide += self.delta_slide * dt;
+            frequency *= (slide * dt).exp2();
+
+            if self.min_frequency > 0.0 && frequency < self.min_frequency {
+                break;
+            }
+
+            let vibrato = 1.0 + self.vibrato_depth * (2.0 * PI * self.vibrato_speed * t).sin();
+            phase += frequency * vibrato * dt;
+
+            // Draw new noise once every period, so that its pitch follows the
+            // frequency like the other waveforms.
+            if phase >= 1.0 {
+                phase -= phase.floor();
+                if self.waveform == Waveform::Noise {
+                    for n in noise.iter_mut() {
+                        *n = rng.gen_range(-1.0, 1.0);
+                    }
+                }
+            }
+
+            let duty = (self.duty + self.duty_sweep * t).max(0.0).min(0.5);
+            let sample = match self.waveform {
+                Waveform::Square => if phase < duty { 1.0 } else { -1.0 },
+                Waveform::Sawtooth => 1.0 - 2.0 * phase,
+                Waveform::Sine => (2.0 * PI * phase).sin(),
+                Waveform::Noise => noise[(phase * NOISE_SAMPLES as f64) as usize % NOISE_SAMPLES],
+            };
+
+            let value = (sample * self.envelope(t) * self.volume) as f32;
+            for _ in 0..channels {
+                out.push(value);
+            }
+        }
+
+        out
+    }
+
+    /// The volume of the sound `t` seconds after it started.
+    fn envelope(&self, t: f64) -> f64 {
+        if t < self.attack {
+            t / self.attack
+        } else if t < self.attack + self.sustain {
+            let progress = (t - self.attack) / self.sustain;
+            1.0 + (1.0 - progress) * 2.0 * self.punch
+        } else {
+            let progress = (t - self.attack - self.sustain) / self.decay;
+            (1.0 - progress).max(0.0)
+        }
+    }
+}
+
@@ -0,0 +1,131 @@
+//! Decoding of Ogg Vorbis files, through the pure-Rust `lewton` crate.
+
+use super::decoder::Decoder;
+use ::lewton::VorbisError;
+use ::lewton::audio::AudioReadError;
+use ::lewton::inside_ogg::OggStreamReader;
+use ::std::io::{Read, Seek};
+
+
+/// Whether `bytes`, the beginning of a file, look like an Ogg file.
+pub fn is_ogg(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && &bytes[0..4] == b"OggS"
+}
+
+
+/// Streams the samples of an Ogg Vorbis file, one packet at a time.
+pub struct VorbisDecoder<R: Read + Seek + Send> {
+    reader: OggStreamReader<R>,
+
+    /// Interleaved samples which were decoded, but not yet read.
+    pending: Vec<f32>,
+
+    /// The index of the first sample of `pending` which was not yet read.
+    position: usize,
+}
+
+impl<R: Read + Seek + Send> VorbisDecoder<R> {
+    pub fn new(reader: R) -> Result<VorbisDecoder<R>, String> {
+        let reader = try!(OggStreamReader::new(reader).map_err(|e| e.to_string()));
+
+        Ok(VorbisDecoder {
+            reader: reader,
+            pending: vec![],
+            position: 0,
+        })
+    }
+
+    fn channels(&self) -> usize {
+        self.reader.ident_hdr.audio_channels as usize
+    }
+
+    /// Decodes the next packet into `pending`. Returns `false` at the end of
+    /// the stream.
+    fn decode_packet(&mut self) -> Result<bool, String> {
+        loop {
+            match self.reader.read_dec_packet_itl() {
+                Ok(Some(samples)) => {
+                    self.pending.extend(samples.iter().map(|&s| s as f32 / 32_768.0));
+                    return Ok(true);
+                },
+                Ok(None) => return Ok(false),
+
+                // The headers are on a page of granule position 0, which is
+                // where seeking to the start of the stream lands.
+                Err(VorbisError::BadAudio(AudioReadError::AudioIsHeader)) => {},
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek + Send> Decoder for VorbisDecoder<R> {
+    fn format(&self) -> (usize, u32) {
+        (self.channels(), self.reader.ident_hdr.audio_sample_rate)
+    }
+
+    fn read(&mut self, frames: usize, out: &mut Vec<f32>) -> Result<usize, String> {
+        let wanted = frames * self.channels();
+
+        while self.pending.len() - self.position < wanted {
+            if !try!(self.decode_packet()) {
+                break;
+            }
+        }
+
+        let end = ::std::cmp::min(self.position + wanted, self.pending.len());
+        out.extend_from_slice(&self.pending[self.position..end]);
+        let read = (end - self.position) / self.channels();
+
+        self.pending.drain(..end);
+        self.position = 0;
+        Ok(read)
+    }
+
+    fn seek(&mut self, frame: u64) -> Result<(), String> {
+        try!(self.reader.seek_absgp_pg(frame).map_err(|e| e.to_string()));
+        self.pending.clear();
+        self.position = 0;
+
+        // Seeking only lands on the Ogg page containing `frame`. The position of
+        // the decoded samples is known once a page has been entirely decoded,
+        // at which point the samples preceding `frame` can be dropped.
+        while try!(self.decode_packet()) {
+            if let Some(page_end) = self.reader.get_last_absgp() {
+                let buffered = (self.pending.len() / self.channels()) as u64;
+                let start = page_end.saturating_sub(buffered);
+                let skipped = ::std::cmp::min(frame.saturating_sub(start), buffered);
+
+                self.position = skipped as usize * self.channels();
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ::phi::audio::decoder::{self, Decoder};
+
+    fn read(decoder: &mut Decoder, frames: usize) -> Vec<f32> {
+        let mut out = vec![];
+        decoder.read(frames, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn seeks_back_to_the_start() {
+        let mut decoder = decoder::open("assets/music/game_intro.ogg").unwrap();
+        assert_eq!(decoder.format(), (1, 22_050));
+
+        let start = read(&mut *decoder, 4_096);
+        assert_eq!(start.len(), 4_096);
+        while read(&mut *decoder, 4_096).len() > 0 {}
+
+        decoder.seek(0).unwrap();
+        assert_eq!(read(&mut *decoder, 4_096), start);
+    }
+}
@@ -0,0 +1,269 @@
+//! A small reader for RIFF/WAVE files, supporting integer and float PCM, and
+//! a writer for float PCM.
+
+use super::convert::{PcmSpec, SampleFormat};
+use ::phi::bytes::{le_bytes, le_value};
+use ::std::fs::File;
+use ::std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The largest `fmt ` chunk accepted. The extensible format only needs 40
+/// bytes, so anything bigger is a corrupted file.
+const MAX_FMT_LEN: u64 = 64;
+
+
+/// What is known about a WAV file after reading its header.
+#[derive(Clone, Copy, Debug)]
+pub struct WavHeader {
+    pub spec: PcmSpec,
+
+    /// The offset of the first sample, in bytes from the start of the file.
+    pub data_offset: u64,
+
+    /// The size of the sample data, in bytes.
+    pub data_len: u64,
+}
+
+impl WavHeader {
+    /// The number of bytes used by a single frame, i.e. a sample for every
+    /// channel.
+    pub fn frame_bytes(&self) -> usize {
+        self.spec.format.bytes() * self.spec.channels
+    }
+
+    /// The number of frames held by the file.
+    pub fn frames(&self) -> u64 {
+        self.data_len / self.frame_bytes() as u64
+    }
+}
+
+
+/// Whether `bytes`, the beginning of a file, look like a WAV file.
+pub fn is_wav(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+}
+
+
+/// Reads the header of a WAV file, leaving `reader` at the first sample.
+pub fn read_header<R: Read + Seek>(reader: &mut R) -> Result<WavHeader, String> {
+    let mut riff = [0u8; 12];
+    try!(reader.read_exact(&mut riff).map_err(|e| e.to_string()));
+    if !is_wav(&riff) {
+        return Err("Not a RIFF/WAVE file".to_string());
+    }
+
+    let mut spec = None;
+
+    loop {
+        let mut chunk = [0u8; 8];
+        try!(reader.read_exact(&mut chunk)
+            .map_err(|_| "Reached the end of the file before the data chunk".to_string()));
+
+        let id = &chunk[0..4];
+        let len = le_value(&chunk[4..8]);
+
+        if id == b"fmt " {
+            if len > MAX_FMT_LEN {
+                return Err(format!("The fmt chunk is too long ({} bytes)", len));
+            }
+
+            let mut fmt = vec![0u8; len as usize];
+            try!(reader.read_exact(&mut fmt).map_err(|e| e.to_string()));
+            spec = Some(try!(parse_fmt(&fmt)));
+
+            if len % 2 == 1 {
+                try!(reader.seek(SeekFrom::Current(1)).map_err(|e| e.to_string()));
+            }
+        } else if id == b"data" {
+            let spec = try!(spec.ok_or("The data chunk comes before the fmt chunk".to_string()));
+            let offset = try!(reader.seek(SeekFrom::Current(0)).map_err(|e| e.to_string()));
+
+            return Ok(WavHeader {
+                spec: spec,
+                data_offset: offset,
+                data_len: len,
+            });
+        } else {
+            // Chunks have an even size, padded if necessary.
+            try!(reader.seek(SeekFrom::Current((len + len % 2) as i64)).map_err(|e| e.to_string()));
+        }
+    }
+}
+
+
+fn parse_fmt(fmt: &[u8]) -> Result<PcmSpec, String> {
+    if fmt.len() < 16 {
+        return Err("The fmt chunk is too short".to_string());
+    }
+
+    let mut tag = le_value(&fmt[0..2]) as u16;
+    let channels = le_value(&fmt[2..4]) as usize;
+    let rate = le_value(&fmt[4..8]) as u32;
+    let bits = le_value(&fmt[14..16]) as u16;
+
+    // The actual format of an extensible file is held by the first two bytes
+    // of its sub-format GUID.
+    if tag == FORMAT_EXTENSIBLE && fmt.len() >= 26 {
+        tag = le_value(&fmt[24..26]) as u16;
+    }
+
+    let format = match (tag, bits) {
+        (FORMAT_PCM, 8) => SampleFormat::U8,
+        (FORMAT_PCM, 16) => SampleFormat::S16,
+        (FORMAT_PCM, 24) => SampleFormat::S24,
+        (FORMAT_PCM, 32) => SampleFormat::S32,
+        (FORMAT_FLOAT, 32) => SampleFormat::F32,
+        _ => return Err(format!("Unsupported WAV format {} with {} bits per sample", tag, bits)),
+    };
+
+    if channels == 0 || rate == 0 {
+        return Err("The WAV file has no channel or a frequency of 0".to_string());
+    }
+
+    Ok(PcmSpec {
+        format: format,
+        channels: channels,
+        rate: rate,
+    })
+}
+
+
+/// Writes interleaved `f32` samples to a WAV file. The sizes in the header are
+/// only filled in once the writer is finished or dropped.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    channels: u16,
+    rate: u32,
+
+    /// The number of bytes of samples written so far.
+    data_len: u64,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, channels: usize, rate: u32) -> Result<WavWriter, String> {
+        let file = try!(File::create(path).map_err(|e| format!("Could not create {}: {}", path, e)));
+
+        let mut writer = WavWriter {
+            writer: BufWriter::new(file),
+            channels: channels as u16,
+            rate: rate,
+            data_len: 0,
+            finished: false,
+        };
+
+        try!(writer.write_header());
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(samples.len() * 4);
+        for &sample in samples {
+            let bits = unsafe { ::std::mem::transmute::<f32, u32>(sample) };
+            bytes.extend_from_slice(&le_bytes(bits as u64, 4));
+        }
+
+        try!(self.writer.write_all(&bytes).map_err(|e| e.to_string()));
+        self.data_len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Fills in the header and flushes the file. Nothing more can be written
+    /// afterwards.
+    pub fn finish(&mut self) -> Result<(), String> {
+        if self.finished {
+            return Ok(());
+        }
+
+        self.finished = true;
+        try!(self.writer.seek(SeekFrom::Start(0)).map_err(|e| e.to_string()));
+        try!(self.write_header());
+        self.writer.flush().map_err(|e| e.to_string())
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        let block_align = self.channels as u32 * 4;
+        let mut header = Vec::with_capacity(44);
+
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&le_bytes(36 + self.data_len, 4));
+        header.extend_from_slice(b"WAVE");
+
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&le_bytes(16, 4));
+        header.extend_from_slice(&le_bytes(FORMAT_FLOAT as u64, 2));
+        header.extend_from_slice(&le_bytes(self.channels as u64, 2));
+        header.extend_from_slice(&le_bytes(self.rate as u64, 4));
+        header.extend_from_slice(&le_bytes((self.rate * block_align) as u64, 4));
+        header.extend_from_slice(&le_bytes(block_align as u64, 2));
+        header.extend_from_slice(&le_bytes(32, 2));
+
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&le_bytes(self.data_len, 4));
+
+        self.writer.write_all(&header).map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            println!("Could not finish writing the WAV file: {}", e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::read_header;
+    use ::phi::audio::convert::SampleFormat;
+    use ::phi::bytes::le_bytes;
+    use ::std::io::Cursor;
+
+    /// The beginning of a WAV file whose `fmt ` chunk is `fmt`, followed by
+    /// an empty data chunk.
+    fn wav(fmt: &[u8]) -> Cursor<Vec<u8>> {
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        bytes.extend_from_slice(&le_bytes(fmt.len() as u64, 4));
+        bytes.extend_from_slice(fmt);
+        bytes.extend_from_slice(b"data\0\0\0\0");
+        Cursor::new(bytes)
+    }
+
+    fn fmt(tag: u16, channels: u16, rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels as u64 * bits as u64 / 8;
+        let mut fmt = vec![];
+        fmt.extend_from_slice(&le_bytes(tag as u64, 2));
+        fmt.extend_from_slice(&le_bytes(channels as u64, 2));
+        fmt.extend_from_slice(&le_bytes(rate as u64, 4));
+        fmt.extend_from_slice(&le_bytes(rate as u64 * block_align, 4));
+        fmt.extend_from_slice(&le_bytes(block_align, 2));
+        fmt.extend_from_slice(&le_bytes(bits as u64, 2));
+        fmt
+    }
+
+    #[test]
+    fn reads_the_format() {
+        let header = read_header(&mut wav(&fmt(1, 2, 22_050, 24))).unwrap();
+        assert_eq!(header.spec.format, SampleFormat::S24);
+        assert_eq!(header.spec.channels, 2);
+        assert_eq!(header.spec.rate, 22_050);
+        assert_eq!(header.data_offset, 44);
+        assert_eq!(header.data_len, 0);
+
+        let header = read_header(&mut wav(&fmt(3, 1, 48_000, 32))).unwrap();
+        assert_eq!(header.spec.format, SampleFormat::F32);
+    }
+
+    #[test]
+    fn rejects_a_huge_fmt_chunk() {
+        let mut file = wav(&fmt(1, 1, 44_100, 16));
+        file.get_mut()[16..20].copy_from_slice(&le_bytes(0xFFFF_FFF0, 4));
+        assert!(read_header(&mut file).is_err());
+    }
+}
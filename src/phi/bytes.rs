@@ -0,0 +1,13 @@
+//! Little-endian numbers, as stored in the files read and written by the game.
+
+
+/// The `len` lowest bytes of `value`, in little-endian order.
+pub fn le_bytes(value: u64, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (value >> (8 * i)) as u8).collect()
+}
+
+
+/// The value of the little-endian number stored in `bytes`.
+pub fn le_value(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64)
+}
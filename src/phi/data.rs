@@ -92,6 +92,11 @@ impl Rectangle {
         ymax >= self.y && ymax <= self.y + self.h as f64
     }
 
+    pub fn contains_point(&self, point: (f64, f64)) -> bool {
+        point.0 >= self.x && point.0 < self.x + self.w &&
+        point.1 >= self.y && point.1 < self.y + self.h
+    }
+
     pub fn overlaps(&self, other: Rectangle) -> bool {
         self.x < other.x + other.w as f64 &&
         self.x + self.w as f64 > other.x &&
@@ -7,8 +7,43 @@ macro_rules! struct_events {
         use ::sdl2::EventPump;
 
 
+        /// The names of the keys declared in `struct_events!`, which are the
+        /// ones actions can be bound to. A key is designated by its index in
+        /// this list.
+        pub const KEY_NAMES: &'static [&'static str] = &[ $( stringify!($k_sdl) ),* ];
+
         pub struct ImmediateEvents {
-            resize: Option<(u32, u32)>,
+            /// The new size of the window, in physical pixels, if it changed.
+            pub resize: Option<(u32, u32)>,
+
+            /// `Some(false)` if the window lost the focus, `Some(true)` if it
+            /// got it back.
+            pub focus: Option<bool>,
+
+            /// `Some(true)` if the window was minimized, `Some(false)` if it
+            /// was restored.
+            pub minimized: Option<bool>,
+
+            /// The buttons of the controllers pressed (`true`) or released
+            /// (`false`) during the frame, by index in `::phi::gamepad::BUTTONS`.
+            pub buttons: Vec<(usize, bool)>,
+
+            /// The new position of the axes which moved during the frame, by
+            /// index in `::phi::gamepad::AXES`.
+            pub axes: Vec<(usize, i16)>,
+
+            /// The new logical position of the mouse, or of the finger
+            /// touching the screen.
+            pub mouse_moved: Option<(f64, f64)>,
+
+            /// The buttons of the mouse pressed (`true`) or released (`false`)
+            /// during the frame, by index in `mouse::BUTTONS`.
+            pub mouse_buttons: Vec<(usize, bool)>,
+
+            /// How far the wheel turned during the frame, positive away from
+            /// the player.
+            pub wheel: i32,
+
             $( pub $k_alias : Option<bool> , )*
             $( pub $e_alias : bool ),*
         }
@@ -17,39 +52,432 @@ macro_rules! struct_events {
             pub fn new() -> ImmediateEvents {
                 ImmediateEvents {
                     resize: None,
+                    focus: None,
+                    minimized: None,
+                    buttons: vec![],
+                    axes: vec![],
+                    mouse_moved: None,
+                    mouse_buttons: vec![],
+                    wheel: 0,
                     $( $k_alias: None , )*
                     $( $e_alias: false ),*
                 }
             }
+
+            /// Whether the key at `key` in `KEY_NAMES` was pressed (`Some(true)`)
+            /// or released (`Some(false)`) during the frame.
+            #[allow(unused_assignments)]
+            pub fn key(&self, key: usize) -> Option<bool> {
+                let mut index = 0;
+                $(
+                    if key == index {
+                        return self.$k_alias;
+                    }
+                    index += 1;
+                )*
+                None
+            }
+
+            /// Whether the button at `button` in `mouse::BUTTONS` was pressed
+            /// during the frame.
+            pub fn clicked(&self, button: usize) -> bool {
+                self.mouse_buttons.contains(&(button, true))
+            }
+
+            /// The changes to the window during the frame, in the order in which
+            /// they are given to `View::on_window_event`.
+            pub fn window_events(&self) -> Vec<WindowEvent> {
+                let mut events = vec![];
+
+                if let Some((w, h)) = self.resize {
+                    events.push(WindowEvent::Resized(w, h));
+                }
+
+                match self.focus {
+                    Some(false) => events.push(WindowEvent::FocusLost),
+                    Some(true) => events.push(WindowEvent::FocusGained),
+                    None => {},
+                }
+
+                match self.minimized {
+                    Some(true) => events.push(WindowEvent::Minimized),
+                    Some(false) => events.push(WindowEvent::Restored),
+                    None => {},
+                }
+
+                events
+            }
+
+            /// Appends the frame to `out`, in the format of replay files.
+            #[allow(unused_assignments)]
+            pub fn encode(&self, out: &mut Vec<u8>) {
+                let mut entries = vec![];
+
+                let mut index = 0u8;
+                $(
+                    if let Some(pressed) = self.$k_alias {
+                        entries.push(index * 2 + pressed as u8);
+                    }
+                    index += 1;
+                )*
+
+                let mut index = ::phi::replay::ELSE_ENTRY;
+                $(
+                    if self.$e_alias {
+                        entries.push(index);
+                    }
+                    index += 1;
+                )*
+
+                if let Some(focused) = self.focus {
+                    entries.push(if focused { ::phi::replay::FOCUS_GAINED_ENTRY } else { ::phi::replay::FOCUS_LOST_ENTRY });
+                }
+
+                if let Some(minimized) = self.minimized {
+                    entries.push(if minimized { ::phi::replay::MINIMIZED_ENTRY } else { ::phi::replay::RESTORED_ENTRY });
+                }
+
+                let count = entries.len() + self.buttons.len() + self.axes.len() +
+                            self.mouse_moved.is_some() as usize + self.mouse_buttons.len() +
+                            (self.wheel != 0) as usize + self.resize.is_some() as usize;
+                out.extend_from_slice(&::phi::bytes::le_bytes(count as u64, 2));
+                out.extend_from_slice(&entries);
+
+                for &(button, pressed) in &self.buttons {
+                    out.push(::phi::replay::BUTTON_ENTRY);
+                    out.push(button as u8 * 2 + pressed as u8);
+                }
+
+                for &(axis, value) in &self.axes {
+                    out.push(::phi::replay::AXIS_ENTRY);
+                    out.push(axis as u8);
+                    out.extend_from_slice(&::phi::bytes::le_bytes(value as u16 as u64, 2));
+                }
+
+                if let Some((x, y)) = self.mouse_moved {
+                    out.push(::phi::replay::MOUSE_MOTION_ENTRY);
+                    out.extend_from_slice(&::phi::bytes::le_bytes(unsafe { ::std::mem::transmute::<f64, u64>(x) }, 8));
+                    out.extend_from_slice(&::phi::bytes::le_bytes(unsafe { ::std::mem::transmute::<f64, u64>(y) }, 8));
+                }
+
+                for &(button, pressed) in &self.mouse_buttons {
+                    out.push(::phi::replay::MOUSE_BUTTON_ENTRY);
+                    out.push(button as u8 * 2 + pressed as u8);
+                }
+
+                if self.wheel != 0 {
+                    out.push(::phi::replay::WHEEL_ENTRY);
+                    out.extend_from_slice(&::phi::bytes::le_bytes(self.wheel as i16 as u16 as u64, 2));
+                }
+
+                if let Some((w, h)) = self.resize {
+                    out.push(::phi::replay::RESIZE_ENTRY);
+                    out.extend_from_slice(&::phi::bytes::le_bytes(w as u64, 4));
+                    out.extend_from_slice(&::phi::bytes::le_bytes(h as u64, 4));
+                }
+            }
+
+            /// Reads a frame written by `encode` from the start of `bytes`.
+            /// Returns it along with the number of bytes it took.
+            #[allow(unused_assignments)]
+            pub fn decode(bytes: &[u8]) -> Result<(ImmediateEvents, usize), String> {
+                let mut now = ImmediateEvents::new();
+                let truncated = || "The frame is truncated".to_string();
+
+                if bytes.len() < 2 {
+                    return Err(truncated());
+                }
+
+                let count = ::phi::bytes::le_value(&bytes[0..2]);
+                let mut read = 2;
+
+                for _ in 0..count {
+                    let code = *try!(bytes.get(read).ok_or_else(&truncated));
+                    read += 1;
+
+                    if code == ::phi::replay::RESIZE_ENTRY {
+                        if bytes.len() < read + 8 {
+                            return Err(truncated());
+                        }
+
+                        let w = ::phi::bytes::le_value(&bytes[read..read + 4]) as u32;
+                        let h = ::phi::bytes::le_value(&bytes[read + 4..read + 8]) as u32;
+                        now.resize = Some((w, h));
+                        read += 8;
+                    } else if code == ::phi::replay::BUTTON_ENTRY {
+                        let entry = *try!(bytes.get(read).ok_or_else(&truncated)) as usize;
+                        if entry / 2 >= ::phi::gamepad::BUTTONS.len() {
+                            return Err(format!("Unknown button {}", entry / 2));
+                        }
+
+                        now.buttons.push((entry / 2, entry % 2 == 1));
+                        read += 1;
+                    } else if code == ::phi::replay::AXIS_ENTRY {
+                        if bytes.len() < read + 3 {
+                            return Err(truncated());
+                        }
+
+                        let axis = bytes[read] as usize;
+                        if axis >= ::phi::gamepad::AXES.len() {
+                            return Err(format!("Unknown axis {}", axis));
+                        }
+
+                        let value = ::phi::bytes::le_value(&bytes[read + 1..read + 3]) as u16 as i16;
+                        now.axes.push((axis, value));
+                        read += 3;
+                    } else if code == ::phi::replay::MOUSE_MOTION_ENTRY {
+                        if bytes.len() < read + 16 {
+                            return Err(truncated());
+                        }
+
+                        let x = unsafe { ::std::mem::transmute::<u64, f64>(::phi::bytes::le_value(&bytes[read..read + 8])) };
+                        let y = unsafe { ::std::mem::transmute::<u64, f64>(::phi::bytes::le_value(&bytes[read + 8..read + 16])) };
+                        now.mouse_moved = Some((x, y));
+                        read += 16;
+                    } else if code == ::phi::replay::MOUSE_BUTTON_ENTRY {
+                        let entry = *try!(bytes.get(read).ok_or_else(&truncated)) as usize;
+                        if entry / 2 >= ::phi::mouse::BUTTONS.len() {
+                            return Err(format!("Unknown mouse button {}", entry / 2));
+                        }
+
+                        now.mouse_buttons.push((entry / 2, entry % 2 == 1));
+                        read += 1;
+                    } else if code == ::phi::replay::WHEEL_ENTRY {
+                        if bytes.len() < read + 2 {
+                            return Err(truncated());
+                        }
+
+                        now.wheel = ::phi::bytes::le_value(&bytes[read..read + 2]) as u16 as i16 as i32;
+                        read += 2;
+                    } else if code == ::phi::replay::FOCUS_LOST_ENTRY {
+                        now.focus = Some(false);
+                    } else if code == ::phi::replay::FOCUS_GAINED_ENTRY {
+                        now.focus = Some(true);
+                    } else if code == ::phi::replay::MINIMIZED_ENTRY {
+                        now.minimized = Some(true);
+                    } else if code == ::phi::replay::RESTORED_ENTRY {
+                        now.minimized = Some(false);
+                    } else if code >= ::phi::replay::ELSE_ENTRY {
+                        let mut index = ::phi::replay::ELSE_ENTRY;
+                        $(
+                            if code == index {
+                                now.$e_alias = true;
+                            }
+                            index += 1;
+                        )*
+                    } else {
+                        let mut index = 0u8;
+                        $(
+                            if code / 2 == index {
+                                now.$k_alias = Some(code % 2 == 1);
+                            }
+                            index += 1;
+                        )*
+                    }
+                }
+
+                Ok((now, read))
+            }
+        }
+
+
+        /// The input hidden by `Events::mask`.
+        pub struct Masked {
+            now: ImmediateEvents,
+            pad: ::phi::gamepad::PadState,
+            mouse_buttons: [bool; 5],
+            $( $k_alias: bool ),*
         }
 
 
         pub struct Events {
-            pump: EventPump,
+            source: Box<::phi::input::InputSource>,
+
+            /// Receives the input of every frame, if it is being recorded.
+            recording: Option<::phi::replay::Recording>,
+
+            /// Provides the input instead of `source` while it is not over.
+            replay: Option<::phi::replay::Replay>,
             pub now: ImmediateEvents,
 
+            /// Whether the window has the focus, and whether it is minimized.
+            pub focused: bool,
+            pub minimized: bool,
+
+            /// The state of the controllers.
+            pub pad: ::phi::gamepad::PadState,
+            gamepads: ::phi::gamepad::Gamepads,
+
+            /// The state of the mouse.
+            pub cursor: ::phi::mouse::Cursor,
+
+            /// The finger which acts as the left button of the mouse while it
+            /// touches the screen.
+            finger: Option<i64>,
+
             // true  => pressed
             // false => not pressed
             $( pub $k_alias: bool ),*
         }
 
         impl Events {
+            /// Reads the events received by the window.
             pub fn new(pump: EventPump) -> Events {
+                Events::with_source(Box::new(pump))
+            }
+
+            pub fn with_source(source: Box<::phi::input::InputSource>) -> Events {
                 Events {
-                    pump: pump,
+                    source: source,
+                    recording: None,
+                    replay: None,
                     now: ImmediateEvents::new(),
+                    focused: true,
+                    minimized: false,
+                    pad: ::phi::gamepad::PadState::new(),
+                    gamepads: ::phi::gamepad::Gamepads::new(None),
+                    cursor: ::phi::mouse::Cursor::new(),
+                    finger: None,
 
                     // By default, initialize every key with _not pressed_
                     $( $k_alias: false ),*
                 }
             }
 
-            pub fn pump(&mut self, renderer: &mut ::sdl2::render::Renderer) {
+            /// Whether the key at `key` in `KEY_NAMES` is held down.
+            #[allow(unused_assignments)]
+            pub fn is_held(&self, key: usize) -> bool {
+                let mut index = 0;
+                $(
+                    if key == index {
+                        return self.$k_alias;
+                    }
+                    index += 1;
+                )*
+                false
+            }
+
+            /// Opens the controllers through `subsystem` when they are plugged
+            /// in. Without it, the controller events still count, but no
+            /// actual device is opened.
+            pub fn open_gamepads(&mut self, subsystem: ::sdl2::GameControllerSubsystem) {
+                self.gamepads = ::phi::gamepad::Gamepads::new(Some(subsystem));
+            }
+
+            /// The number of controllers plugged in.
+            pub fn gamepads(&self) -> usize {
+                self.gamepads.len()
+            }
+
+            /// Reads the events from `source` from now on, and returns the
+            /// previous source. The state of the keys is kept.
+            pub fn set_source(&mut self, source: Box<::phi::input::InputSource>) -> Box<::phi::input::InputSource> {
+                ::std::mem::replace(&mut self.source, source)
+            }
+
+            /// Adds the input of every frame to `recording`, from now on.
+            pub fn record(&mut self, recording: ::phi::replay::Recording) {
+                self.recording = Some(recording);
+            }
+
+            /// Stops recording, and returns what was recorded.
+            pub fn take_recording(&mut self) -> Option<::phi::replay::Recording> {
+                self.recording.take()
+            }
+
+            /// Reads the input from `replay` instead of the source until it is
+            /// over. The events of the source are ignored meanwhile.
+            pub fn replay(&mut self, replay: ::phi::replay::Replay) {
+                self.replay = Some(replay);
+            }
+
+            pub fn is_replaying(&self) -> bool {
+                self.replay.as_ref().map_or(false, |replay| !replay.is_over())
+            }
+
+            /// Hides the input, as if no key was pressed, until `unmask` is
+            /// called. Used to update the views which are covered by another
+            /// one, so that they do not react to the keys meant for it. The
+            /// mouse keeps its position, but its buttons are released.
+            pub fn mask(&mut self) -> Masked {
+                Masked {
+                    now: ::std::mem::replace(&mut self.now, ImmediateEvents::new()),
+                    pad: ::std::mem::replace(&mut self.pad, ::phi::gamepad::PadState::new()),
+                    mouse_buttons: ::std::mem::replace(&mut self.cursor.buttons, [false; 5]),
+                    $( $k_alias: ::std::mem::replace(&mut self.$k_alias, false) ),*
+                }
+            }
+
+            /// Restores the input hidden by `mask`.
+            pub fn unmask(&mut self, masked: Masked) {
+                self.now = masked.now;
+                self.pad = masked.pad;
+                self.cursor.buttons = masked.mouse_buttons;
+                $( self.$k_alias = masked.$k_alias; )*
+            }
+
+            /// Reads the input of the next frame. The position of the mouse
+            /// is converted to logical pixels through `viewport`.
+            pub fn pump(&mut self, renderer: &mut ::sdl2::render::Renderer, viewport: &::phi::scaling::Viewport) {
                 self.now = ImmediateEvents::new();
+                self.pad.axes_before = self.pad.axes;
+
+                let replayed = self.replay.as_mut().and_then(|replay| replay.next_frame());
+                match replayed {
+                    Some(now) => {
+                        // Keep the window responsive, but follow the replay.
+                        // Closing the window or pressing Escape still quits.
+                        let events = self.source.poll();
+                        self.now = now;
+
+                        if let Some(focused) = self.now.focus {
+                            self.focused = focused;
+                        }
+                        if let Some(minimized) = self.now.minimized {
+                            self.minimized = minimized;
+                        }
+
+                        for &(button, pressed) in &self.now.buttons {
+                            self.pad.buttons[button] = pressed;
+                        }
+                        for &(axis, value) in &self.now.axes {
+                            self.pad.axes[axis] = value;
+                        }
+
+                        if let Some(position) = self.now.mouse_moved {
+                            self.cursor.position = position;
+                        }
+                        for &(button, pressed) in &self.now.mouse_buttons {
+                            self.cursor.buttons[button] = pressed;
+                        }
+
+                        $(
+                            if let Some(pressed) = self.now.$k_alias {
+                                self.$k_alias = pressed;
+                            }
+                        )*
+
+                        for event in events {
+                            match event {
+                                ::sdl2::event::Event::Quit { .. } |
+                                ::sdl2::event::Event::KeyDown { keycode: Some(::sdl2::keyboard::Keycode::Escape), .. } =>
+                                    self.now.quit = true,
+                                _ => {}
+                            }
+                        }
+                    },
+                    None => self.pump_source(renderer, viewport),
+                }
 
-                for event in self.pump.poll_iter() {
+                if let Some(ref mut recording) = self.recording {
+                    recording.push(&self.now);
+                }
+            }
+
+            fn pump_source(&mut self, renderer: &mut ::sdl2::render::Renderer, viewport: &::phi::scaling::Viewport) {
+                for event in self.source.poll() {
                     use ::sdl2::event::Event::*;
-                    use ::sdl2::event::WindowEventId::Resized;
+                    use ::sdl2::event::WindowEventId::{FocusGained, FocusLost, Minimized, Resized, Restored};
                     use ::sdl2::keyboard::Keycode::*;
 
                     match event {
@@ -57,6 +485,124 @@ macro_rules! struct_events {
                             self.now.resize = Some(renderer.output_size().unwrap());
                         },
 
+                        Window { win_event_id: FocusLost, .. } => {
+                            self.now.focus = Some(false);
+                            self.focused = false;
+                        },
+
+                        Window { win_event_id: FocusGained, .. } => {
+                            self.now.focus = Some(true);
+                            self.focused = true;
+                        },
+
+                        Window { win_event_id: Minimized, .. } => {
+                            self.now.minimized = Some(true);
+                            self.minimized = true;
+                        },
+
+                        // Also sent when a maximized window is restored, which
+                        // does not concern the views.
+                        Window { win_event_id: Restored, .. } => {
+                            if self.minimized {
+                                self.now.minimized = Some(false);
+                                self.minimized = false;
+                            }
+                        },
+
+                        ControllerDeviceAdded { which, .. } => {
+                            self.gamepads.open(which);
+                        },
+
+                        // Once the last controller is unplugged, whatever it
+                        // held is released, so that the ship does not keep
+                        // moving or firing.
+                        ControllerDeviceRemoved { .. } => {
+                            self.gamepads.close_detached();
+                            if self.gamepads.len() == 0 {
+                                self.release_pad();
+                            }
+                        },
+
+                        ControllerButtonDown { button, .. } => {
+                            let button = ::phi::gamepad::button_index(button);
+                            if !self.pad.buttons[button] {
+                                self.now.buttons.push((button, true));
+                            }
+                            self.pad.buttons[button] = true;
+                        },
+
+                        ControllerButtonUp { button, .. } => {
+                            let button = ::phi::gamepad::button_index(button);
+                            self.now.buttons.push((button, false));
+                            self.pad.buttons[button] = false;
+                        },
+
+                        // Only the last position of an axis during the frame
+                        // matters.
+                        ControllerAxisMotion { axis, value, .. } => {
+                            let axis = ::phi::gamepad::axis_index(axis);
+                            self.now.axes.retain(|&(moved, _)| moved != axis);
+                            self.now.axes.push((axis, value));
+                            self.pad.axes[axis] = value;
+                        },
+
+                        // SDL also turns touches into mouse events, which
+                        // would count them twice.
+                        MouseMotion { which: ::phi::mouse::TOUCH_MOUSE_ID, .. } |
+                        MouseButtonDown { which: ::phi::mouse::TOUCH_MOUSE_ID, .. } |
+                        MouseButtonUp { which: ::phi::mouse::TOUCH_MOUSE_ID, .. } => {},
+
+                        MouseMotion { x, y, .. } => {
+                            self.move_cursor(viewport.clamp_to_logical(x, y));
+                        },
+
+                        // The mouse may have moved since the last motion event.
+                        MouseButtonDown { mouse_btn, x, y, .. } => {
+                            self.move_cursor(viewport.clamp_to_logical(x, y));
+                            if let Some(button) = ::phi::mouse::button_index(mouse_btn) {
+                                self.press_mouse(button, true);
+                            }
+                        },
+
+                        MouseButtonUp { mouse_btn, x, y, .. } => {
+                            self.move_cursor(viewport.clamp_to_logical(x, y));
+                            if let Some(button) = ::phi::mouse::button_index(mouse_btn) {
+                                self.press_mouse(button, false);
+                            }
+                        },
+
+                        // The first finger to touch the logical screen acts as
+                        // the left button of the mouse, until it is lifted.
+                        // Touches on the bars around it are ignored.
+                        FingerDown { finger_id, x, y, .. } => {
+                            if self.finger.is_none() {
+                                if let Some(position) = viewport.touch_to_logical(x, y) {
+                                    self.finger = Some(finger_id);
+                                    self.move_cursor(position);
+                                    self.press_mouse(::phi::mouse::LEFT, true);
+                                }
+                            }
+                        },
+
+                        FingerMotion { finger_id, x, y, .. } => {
+                            if self.finger == Some(finger_id) {
+                                if let Some(position) = viewport.touch_to_logical(x, y) {
+                                    self.move_cursor(position);
+                                }
+                            }
+                        },
+
+                        FingerUp { finger_id, .. } => {
+                            if self.finger == Some(finger_id) {
+                                self.finger = None;
+                                self.press_mouse(::phi::mouse::LEFT, false);
+                            }
+                        },
+
+                        MouseWheel { y, .. } => {
+                            self.now.wheel += y;
+                        },
+
                         KeyDown { keycode, .. } => match keycode {
                             //
                             $(
@@ -92,6 +638,40 @@ macro_rules! struct_events {
                     }
                 }
             }
+
+            /// Releases the buttons and centers the axes of the controllers,
+            /// as if the player had let go of them during the frame.
+            fn release_pad(&mut self) {
+                for button in 0..self.pad.buttons.len() {
+                    if self.pad.buttons[button] {
+                        self.now.buttons.push((button, false));
+                        self.pad.buttons[button] = false;
+                    }
+                }
+
+                for axis in 0..self.pad.axes.len() {
+                    if self.pad.axes[axis] != 0 {
+                        self.now.axes.retain(|&(moved, _)| moved != axis);
+                        self.now.axes.push((axis, 0));
+                        self.pad.axes[axis] = 0;
+                    }
+                }
+            }
+
+            fn press_mouse(&mut self, button: usize, pressed: bool) {
+                // A button already held down is not pressed again.
+                if !pressed || !self.cursor.buttons[button] {
+                    self.now.mouse_buttons.push((button, pressed));
+                }
+                self.cursor.buttons[button] = pressed;
+            }
+
+            fn move_cursor(&mut self, position: (f64, f64)) {
+                if position != self.cursor.position {
+                    self.now.mouse_moved = Some(position);
+                    self.cursor.position = position;
+                }
+            }
         }
     }
 }
@@ -0,0 +1,153 @@
+//! Game controllers. They are opened when they are plugged in, and their
+//! buttons and sticks feed the same actions as the keyboard.
+//!
+//! Buttons and axes are designated by their index in `BUTTONS` and `AXES`,
+//! which is also how they are stored in replays.
+
+use ::sdl2::GameControllerSubsystem;
+use ::sdl2::controller::{Axis, Button, GameController};
+
+
+pub const BUTTONS: &'static [Button] = &[
+    Button::A, Button::B, Button::X, Button::Y,
+    Button::Back, Button::Guide, Button::Start,
+    Button::LeftStick, Button::RightStick,
+    Button::LeftShoulder, Button::RightShoulder,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+
+/// The names of the buttons in configuration files, which are the ones SDL
+/// uses in its controller mappings.
+pub const BUTTON_NAMES: &'static [&'static str] = &[
+    "a", "b", "x", "y",
+    "back", "guide", "start",
+    "leftstick", "rightstick",
+    "leftshoulder", "rightshoulder",
+    "dpup", "dpdown", "dpleft", "dpright",
+];
+
+pub const AXES: &'static [Axis] = &[
+    Axis::LeftX, Axis::LeftY, Axis::RightX, Axis::RightY,
+    Axis::TriggerLeft, Axis::TriggerRight,
+];
+
+pub const AXIS_NAMES: &'static [&'static str] = &[
+    "leftx", "lefty", "rightx", "righty", "lefttrigger", "righttrigger",
+];
+
+/// The fraction of its course a stick must be pushed to count as pressed,
+/// for instance to move through a menu.
+pub const PRESS_THRESHOLD: f64 = 0.5;
+
+/// The default fraction of their course sticks can move without any effect,
+/// since they never rest exactly at their center.
+pub const DEFAULT_DEADZONE: f64 = 0.25;
+
+
+/// The buttons held down and the position of the axes of the controllers.
+/// When several controllers are plugged in, they all act as one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PadState {
+    pub buttons: [bool; 15],
+    pub axes: [i16; 6],
+
+    /// The position of the axes at the end of the previous frame, to tell
+    /// when a stick is pushed past `PRESS_THRESHOLD`.
+    pub axes_before: [i16; 6],
+}
+
+
+/// The controllers which are plugged in.
+pub struct Gamepads {
+    /// `None` when the events do not come from SDL, in which case there is
+    /// nothing to open.
+    subsystem: Option<GameControllerSubsystem>,
+    opened: Vec<GameController>,
+}
+
+
+impl PadState {
+    pub fn new() -> PadState {
+        PadState {
+            buttons: [false; 15],
+            axes: [0; 6],
+            axes_before: [0; 6],
+        }
+    }
+
+    /// The position of the axis at `axis` in `AXES`, between -1 and 1, or 0
+    /// in the deadzone.
+    pub fn axis(&self, axis: usize, deadzone: f64) -> f64 {
+        normalize(self.axes[axis], deadzone)
+    }
+
+    pub fn axis_before(&self, axis: usize, deadzone: f64) -> f64 {
+        normalize(self.axes_before[axis], deadzone)
+    }
+}
+
+
+impl Gamepads {
+    pub fn new(subsystem: Option<GameControllerSubsystem>) -> Gamepads {
+        Gamepads {
+            subsystem: subsystem,
+            opened: vec![],
+        }
+    }
+
+    /// Opens the controller at `index`, as given by SDL when it is plugged in.
+    pub fn open(&mut self, index: i32) {
+        let subsystem = match self.subsystem {
+            Some(ref subsystem) => subsystem,
+            None => return,
+        };
+
+        if index < 0 || !subsystem.is_game_controller(index as u32) {
+            return;
+        }
+
+        match subsystem.open(index as u32) {
+            Ok(controller) => {
+                println!("Connected the controller {}", controller.name());
+                self.opened.push(controller);
+            },
+            Err(e) => println!("Could not open the controller {}: {}", index, e),
+        }
+    }
+
+    /// Closes the controllers which were unplugged.
+    pub fn close_detached(&mut self) {
+        let before = self.opened.len();
+        self.opened.retain(|controller| controller.attached());
+
+        if self.opened.len() < before {
+            println!("Disconnected {} controller(s)", before - self.opened.len());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.opened.len()
+    }
+}
+
+
+pub fn button_index(button: Button) -> usize {
+    BUTTONS.iter().position(|&b| b == button).unwrap()
+}
+
+pub fn axis_index(axis: Axis) -> usize {
+    AXES.iter().position(|&a| a == axis).unwrap()
+}
+
+
+/// Maps the raw position of an axis to [-1, 1], ignoring the deadzone around
+/// the center and stretching the rest, so that movements start smoothly.
+pub fn normalize(value: i16, deadzone: f64) -> f64 {
+    let value = (value as f64 / 32767.0).max(-1.0).min(1.0);
+
+    if value.abs() <= deadzone {
+        0.0
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1.0 - deadzone)
+    }
+}
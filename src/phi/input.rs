@@ -0,0 +1,290 @@
+//! Where the events read by `Events::pump` come from: either the window, or a
+//! script given in advance, so that the game can be driven without a player,
+//! or a controller, a mouse or a touch screen without an actual device.
+
+use ::sdl2::EventPump;
+use ::sdl2::controller::{Axis, Button};
+use ::sdl2::event::{Event, WindowEventId};
+use ::sdl2::keyboard::{Keycode, NOMOD};
+use ::sdl2::mouse::{Mouse, MouseState};
+
+
+/// Provides the events handled by `Events` on every call to `pump`.
+pub trait InputSource {
+    /// Returns the events which happened since the last call.
+    fn poll(&mut self) -> Vec<Event>;
+}
+
+
+/// Events decided in advance. The `n`-th call to `poll` returns the events
+/// scripted for frame `n`, counting from 0, and nothing once the script is
+/// over.
+pub struct Script {
+    frames: Vec<Vec<Event>>,
+
+    /// The frame whose events are returned by the next call to `poll`.
+    next: usize,
+}
+
+
+impl InputSource for EventPump {
+    fn poll(&mut self) -> Vec<Event> {
+        self.poll_iter().collect()
+    }
+}
+
+
+impl Script {
+    pub fn new() -> Script {
+        Script {
+            frames: vec![],
+            next: 0,
+        }
+    }
+
+    /// Adds `event` to the ones received on `frame`, after those which were
+    /// already scripted for it.
+    pub fn at(mut self, frame: usize, event: Event) -> Script {
+        self.push(frame, event);
+        self
+    }
+
+    pub fn push(&mut self, frame: usize, event: Event) {
+        while self.frames.len() <= frame {
+            self.frames.push(vec![]);
+        }
+        self.frames[frame].push(event);
+    }
+
+    /// Whether every scripted event was returned.
+    pub fn is_over(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+}
+
+impl InputSource for Script {
+    fn poll(&mut self) -> Vec<Event> {
+        let events = match self.frames.get_mut(self.next) {
+            Some(events) => ::std::mem::replace(events, vec![]),
+            None => vec![],
+        };
+
+        self.next += 1;
+        events
+    }
+}
+
+
+/// The event sent by SDL when `keycode` is pressed.
+pub fn key_down(keycode: Keycode) -> Event {
+    Event::KeyDown {
+        timestamp: 0,
+        window_id: 0,
+        keycode: Some(keycode),
+        scancode: None,
+        keymod: NOMOD,
+        repeat: false,
+    }
+}
+
+/// The event sent by SDL when `keycode` is released.
+pub fn key_up(keycode: Keycode) -> Event {
+    Event::KeyUp {
+        timestamp: 0,
+        window_id: 0,
+        keycode: Some(keycode),
+        scancode: None,
+        keymod: NOMOD,
+        repeat: false,
+    }
+}
+
+/// The event sent by SDL when the player closes the window.
+pub fn quit() -> Event {
+    Event::Quit { timestamp: 0 }
+}
+
+/// The event sent by SDL when the window is resized to `width` by `height`.
+pub fn resize(width: i32, height: i32) -> Event {
+    Event::Window {
+        timestamp: 0,
+        window_id: 0,
+        win_event_id: WindowEventId::Resized,
+        data1: width,
+        data2: height,
+    }
+}
+
+/// The event sent by SDL when finger `id` touches the screen at (`x`, `y`),
+/// as fractions of the size of the window.
+pub fn finger_down(id: i64, x: f32, y: f32) -> Event {
+    Event::FingerDown {
+        timestamp: 0,
+        touch_id: 0,
+        finger_id: id,
+        x: x,
+        y: y,
+        dx: 0.0,
+        dy: 0.0,
+        pressure: 1.0,
+    }
+}
+
+/// The event sent by SDL when finger `id` moves to (`x`, `y`), as fractions of
+/// the size of the window.
+pub fn finger_motion(id: i64, x: f32, y: f32) -> Event {
+    Event::FingerMotion {
+        timestamp: 0,
+        touch_id: 0,
+        finger_id: id,
+        x: x,
+        y: y,
+        dx: 0.0,
+        dy: 0.0,
+        pressure: 1.0,
+    }
+}
+
+/// The event sent by SDL when finger `id` is lifted at (`x`, `y`), as
+/// fractions of the size of the window.
+pub fn finger_up(id: i64, x: f32, y: f32) -> Event {
+    Event::FingerUp {
+        timestamp: 0,
+        touch_id: 0,
+        finger_id: id,
+        x: x,
+        y: y,
+        dx: 0.0,
+        dy: 0.0,
+        pressure: 0.0,
+    }
+}
+
+/// The event sent by SDL when the window loses the focus.
+pub fn focus_lost() -> Event {
+    window_event(WindowEventId::FocusLost)
+}
+
+/// The event sent by SDL when the window gets the focus back.
+pub fn focus_gained() -> Event {
+    window_event(WindowEventId::FocusGained)
+}
+
+/// The event sent by SDL when the window is minimized.
+pub fn minimize() -> Event {
+    window_event(WindowEventId::Minimized)
+}
+
+/// The event sent by SDL when the window is restored.
+pub fn restore() -> Event {
+    window_event(WindowEventId::Restored)
+}
+
+/// The event sent by SDL when the controller at `index` is plugged in. With a
+/// script, no controller is actually opened.
+pub fn controller_added(index: i32) -> Event {
+    Event::ControllerDeviceAdded {
+        timestamp: 0,
+        which: index,
+    }
+}
+
+/// The event sent by SDL when a controller is unplugged.
+pub fn controller_removed(id: i32) -> Event {
+    Event::ControllerDeviceRemoved {
+        timestamp: 0,
+        which: id,
+    }
+}
+
+/// The event sent by SDL when `button` of a controller is pressed.
+pub fn button_down(button: Button) -> Event {
+    Event::ControllerButtonDown {
+        timestamp: 0,
+        which: 0,
+        button: button,
+    }
+}
+
+/// The event sent by SDL when `button` of a controller is released.
+pub fn button_up(button: Button) -> Event {
+    Event::ControllerButtonUp {
+        timestamp: 0,
+        which: 0,
+        button: button,
+    }
+}
+
+/// The event sent by SDL when `axis` of a controller moves to `value`, from
+/// -32768 to 32767.
+pub fn axis_motion(axis: Axis, value: i16) -> Event {
+    Event::ControllerAxisMotion {
+        timestamp: 0,
+        which: 0,
+        axis: axis,
+        value: value,
+    }
+}
+
+/// The event sent by SDL when the mouse moves to (`x`, `y`), in pixels of the
+/// window.
+pub fn mouse_motion(x: i32, y: i32) -> Event {
+    Event::MouseMotion {
+        timestamp: 0,
+        window_id: 0,
+        which: 0,
+        mousestate: MouseState::from_flags(0),
+        x: x,
+        y: y,
+        xrel: 0,
+        yrel: 0,
+    }
+}
+
+/// The event sent by SDL when `button` of the mouse is pressed at (`x`, `y`),
+/// in pixels of the window.
+pub fn mouse_down(button: Mouse, x: i32, y: i32) -> Event {
+    Event::MouseButtonDown {
+        timestamp: 0,
+        window_id: 0,
+        which: 0,
+        mouse_btn: button,
+        x: x,
+        y: y,
+    }
+}
+
+/// The event sent by SDL when `button` of the mouse is released at (`x`, `y`),
+/// in pixels of the window.
+pub fn mouse_up(button: Mouse, x: i32, y: i32) -> Event {
+    Event::MouseButtonUp {
+        timestamp: 0,
+        window_id: 0,
+        which: 0,
+        mouse_btn: button,
+        x: x,
+        y: y,
+    }
+}
+
+/// The event sent by SDL when the wheel of the mouse turns by `y` notches,
+/// positive away from the player.
+pub fn wheel(y: i32) -> Event {
+    Event::MouseWheel {
+        timestamp: 0,
+        window_id: 0,
+        which: 0,
+        x: 0,
+        y: y,
+    }
+}
+
+fn window_event(id: WindowEventId) -> Event {
+    Event::Window {
+        timestamp: 0,
+        window_id: 0,
+        win_event_id: id,
+        data1: 0,
+        data2: 0,
+    }
+}
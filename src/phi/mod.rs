@@ -1,11 +1,42 @@
 #[macro_use]
 mod events;
+pub mod actions;
+pub mod audio;
+pub mod bytes;
 pub mod data;
+pub mod gamepad;
 pub mod gfx;
-
+pub mod input;
+pub mod mouse;
+pub mod random;
+pub mod replay;
+pub mod scaling;
+pub mod stack;
+#[cfg(test)]
+pub mod testing;
+pub mod timing;
+pub mod trace;
+pub mod transition;
+pub mod window;
+
+use self::actions::{Action, Bindings};
+use self::audio::{Audio, AudioOutput};
+use self::audio::bank::SoundBank;
+use self::audio::music::Playlist;
 use self::gfx::Sprite;
+use self::input::{InputSource, Script};
+use self::random::Random;
+use self::replay::Recording;
+use self::scaling::Viewport;
+use self::stack::ViewStack;
+use self::timing::FixedStep;
+use self::trace::{StateHasher, Tracer};
+use self::transition::Transition;
+use self::window::{WindowConfig, WindowMode};
+use ::sdl2::Sdl;
 use ::sdl2::render::Renderer;
 use ::sdl2::pixels::Color;
+use ::std::any::Any;
 use ::std::collections::HashMap;
 use ::std::path::Path;
 
@@ -22,7 +53,23 @@ struct_events! {
 
         key_1: Num1,
         key_2: Num2,
-        key_3: Num3
+        key_3: Num3,
+
+        key_f11: F11,
+
+        key_w: W,
+        key_a: A,
+        key_s: S,
+        key_d: D,
+        key_z: Z,
+        key_x: X,
+        key_c: C,
+        key_p: P,
+        key_tab: Tab,
+        key_backspace: Backspace,
+        key_lshift: LShift,
+        key_lctrl: LCtrl,
+        key_rctrl: RCtrl
     },
     else: {
         quit: Quit { .. }
@@ -30,31 +77,198 @@ struct_events! {
 }
 
 
+/// Options given to `spawn_with`.
+pub struct Config {
+    /// How the window is shown.
+    pub window: WindowConfig,
+
+    /// The size of the screen the views draw into, in logical pixels. It is
+    /// scaled to the window as `window.scaling` says.
+    pub logical_size: (u32, u32),
+
+    /// Where the sounds go.
+    pub audio: AudioOutput,
+
+    /// The number of simulation steps per second.
+    pub step_rate: f64,
+
+    /// If given, the events are read from this script, one frame of it per
+    /// simulation step, instead of from the window.
+    pub script: Option<Script>,
+
+    /// The seed of the random number generators. A different one is picked
+    /// on every run if it is not given.
+    pub seed: Option<u64>,
+
+    /// If given, the input is recorded, and saved to this file when the game
+    /// stops.
+    pub record: Option<String>,
+
+    /// If given, the input is read from this recording until it is over. Its
+    /// seed, step rate and bindings replace the ones of the configuration.
+    pub replay: Option<Recording>,
+
+    /// The keys bound to the actions of the player.
+    pub bindings: Bindings,
+
+    /// If given, the bindings are saved to this file when the game stops, if
+    /// they were changed.
+    pub bindings_path: Option<String>,
+
+    /// If given, the hash of the state of the views after every step is
+    /// written to this trace file.
+    pub trace: Option<String>,
+
+    /// If given, the hash of the state after every step is compared to the
+    /// ones of this trace file, and the first frame which differs reported.
+    pub verify: Option<String>,
+}
+
+impl Config {
+    /// Plays the sounds through the default device, in the default window.
+    pub fn new() -> Config {
+        Config {
+            window: WindowConfig::new(),
+            logical_size: (800, 600),
+            audio: AudioOutput::Device,
+            step_rate: timing::DEFAULT_STEP_RATE,
+            script: None,
+            seed: None,
+            record: None,
+            replay: None,
+            trace: None,
+            verify: None,
+            bindings: Bindings::new(),
+            bindings_path: None,
+        }
+    }
+}
+
+
 /// Bundles the Phi abstractions in a single structure which
 /// can be passed easily between functions.
 pub struct Phi<'window> {
     pub events: Events,
     pub renderer: Renderer<'window>,
+    pub audio: Audio,
+    pub sounds: SoundBank,
+
+    /// The random number generators used by the game. They are seeded, so
+    /// that runs can be reproduced: the global `rand::random` must not be
+    /// used.
+    pub random: Random,
+
+    /// The hashes of the state of the game, frame by frame.
+    pub trace: Tracer,
+
+    /// The keys bound to the actions of the player, which views should query
+    /// rather than the keys themselves.
+    pub bindings: Bindings,
+
+    /// The options of the window, kept up to date when they are changed.
+    window: WindowConfig,
+
+    /// Where the logical screen lies in the window.
+    viewport: Viewport,
 
     cached_fonts: HashMap<(&'static str, i32), ::sdl2_ttf::Font>,
 }
 
 impl<'window> Phi<'window> {
-    fn new(events: Events, renderer: Renderer<'window>) -> Phi<'window> {
+    fn new(events: Events, renderer: Renderer<'window>, audio: Audio,
+           window: WindowConfig, viewport: Viewport, seed: u64) -> Phi<'window> {
         ::sdl2_image::init(::sdl2_image::INIT_PNG);
 
         Phi {
             events: events,
             renderer: renderer,
+            sounds: SoundBank::new(audio.spec()),
+            audio: audio,
+            random: Random::new(seed),
+            trace: Tracer::new(),
+            bindings: Bindings::new(),
+            window: window,
+            viewport: viewport,
             cached_fonts: HashMap::new(),
         }
     }
 
+    /// The seed from which every random number is derived.
+    pub fn seed(&self) -> u64 {
+        self.random.seed()
+    }
+
+    /// Whether the player requested `action` during this frame.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings.pressed(&self.events, action)
+    }
+
+    /// Whether the player stopped requesting `action` during this frame.
+    pub fn released(&self, action: Action) -> bool {
+        self.bindings.released(&self.events, action)
+    }
+
+    /// Whether the player keeps requesting `action`, for instance by holding
+    /// one of its keys down.
+    pub fn held(&self, action: Action) -> bool {
+        self.bindings.held(&self.events, action)
+    }
+
+    /// How much the player requests `action`, between 0 and 1, for instance
+    /// how far a stick is pushed.
+    pub fn strength(&self, action: Action) -> f64 {
+        self.bindings.strength(&self.events, action)
+    }
+
+    /// The size of the logical screen, which does not depend on the size of
+    /// the window. Views should lay everything out in it.
     pub fn output_size(&self) -> (f64, f64) {
-        let (w, h) = self.renderer.output_size().unwrap();
+        let (w, h) = self.viewport.logical_size();
         (w as f64, h as f64)
     }
 
+    /// Where the logical screen lies in the window, to convert positions in
+    /// the window to logical ones.
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    /// Fits the logical screen to the current size of the window.
+    fn fit_viewport(&mut self) {
+        let (w, h) = self.renderer.output_size().unwrap();
+        self.viewport.fit(w, h);
+    }
+
+    /// The current options of the window.
+    pub fn window_config(&self) -> &WindowConfig {
+        &self.window
+    }
+
+    /// Shows the window in `mode`, without restarting the game.
+    pub fn set_window_mode(&mut self, mode: WindowMode) -> Result<(), String> {
+        try!(window::set_mode(&mut self.renderer, mode));
+        self.window.mode = mode;
+        Ok(())
+    }
+
+    /// Switches between a window and a borderless one covering the screen.
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let mode = match self.window.mode {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Fullscreen | WindowMode::Borderless => WindowMode::Windowed,
+        };
+        self.set_window_mode(mode)
+    }
+
+    /// Resizes the window, or changes the resolution of the screen in
+    /// fullscreen mode, without restarting the game.
+    pub fn set_resolution(&mut self, width: u32, height: u32) -> Result<(), String> {
+        try!(window::set_size(&mut self.renderer, self.window.mode, width, height));
+        self.window.width = width;
+        self.window.height = height;
+        Ok(())
+    }
+
 
     /// Renders a string of text as a sprite using the provided parameters.
     pub fn ttf_str_sprite(&mut self, text: &str, font_path: &'static str, size: i32, color: Color) -> Option<Sprite> {
@@ -85,16 +299,115 @@ impl<'window> Drop for Phi<'window> {
 pub enum ViewAction {
     None,
     Quit,
+
+    /// Exits every view and starts over with the given one.
     ChangeView(Box<View>),
+
+    /// Same as `ChangeView`, but the new view is revealed progressively over
+    /// the old ones, which are only exited once the transition is over.
+    Transition(Box<View>, Transition),
+
+    /// Shows a view on top of the current one, which is kept for later.
+    Push(Box<View>),
+
+    /// Exits the top view, going back to the one below it. The game stops if
+    /// there is none.
+    Pop,
+
+    /// Exits the top view and shows the given one in its place.
+    Replace(Box<View>),
 }
 
 
-pub trait View {
-    /// Called on every frame to take care of both the logic and
-    /// the rendering of the current view.
+/// A change to the window, given to every view by `View::on_window_event`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowEvent {
+    /// The window was resized to the given size, in physical pixels. The
+    /// logical screen keeps its size.
+    Resized(u32, u32),
+
+    FocusLost,
+    FocusGained,
+    Minimized,
+    Restored,
+}
+
+
+/// Gives access to a view as `Any`, so that it can be downcast to its actual
+/// type, for instance to inspect it after `spawn_headless`.
+pub trait AsAny {
+    fn as_any(&self) -> &Any;
+    fn as_any_mut(&mut self) -> &mut Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut Any {
+        self
+    }
+}
+
+
+pub trait View: AsAny {
+    /// Called on every simulation step to handle the input and move things
+    /// around.
     ///
-    /// `elapsed` is expressed in seconds.
-    fn render(&mut self, context: &mut Phi, elapsed: f64) -> ViewAction;
+    /// `dt` is expressed in seconds, and is the same for every step.
+    fn update(&mut self, context: &mut Phi, dt: f64) -> ViewAction;
+
+    /// Called once per frame, after the steps of the frame were simulated, to
+    /// draw the view. `alpha`, between 0 and 1, tells how far the game is into
+    /// the next step, so that moving objects can be drawn in between their
+    /// last two positions.
+    fn render(&self, context: &mut Phi, alpha: f64);
+
+    /// Called when the view becomes the current one, before its first update.
+    fn on_enter(&mut self, _context: &mut Phi) {}
+
+    /// Called when the view stops being the current one, after its last
+    /// update. The view is dropped right afterwards.
+    fn on_exit(&mut self, _context: &mut Phi) {}
+
+    /// Called when the view stops being updated for a while, without being
+    /// exited, because a view was pushed on top of it.
+    fn pause(&mut self, _context: &mut Phi) {}
+
+    /// Called when a paused view is updated again.
+    fn resume(&mut self, _context: &mut Phi) {}
+
+    /// Called on every view of the stack, from the bottom to the top, when
+    /// the window changes, before the views are updated. Only the action
+    /// requested by the top view is applied.
+    fn on_window_event(&mut self, _context: &mut Phi, _event: WindowEvent) -> ViewAction {
+        ViewAction::None
+    }
+
+    /// Whether the views below this one are still drawn while it is shown,
+    /// for instance because it does not cover the whole screen.
+    fn renders_below(&self) -> bool {
+        false
+    }
+
+    /// Whether the views below this one keep being updated while it is shown.
+    /// They do not receive any input, which is reserved to the top view.
+    fn updates_below(&self) -> bool {
+        false
+    }
+
+    /// The music played while the view is shown. When the game changes to a
+    /// view with a different playlist, the music crossfades to it.
+    fn music(&self) -> Option<Playlist> {
+        None
+    }
+
+    /// Feeds the state of the simulation to `hasher`, after every step. Two
+    /// runs which hash to the same value on every frame are considered the
+    /// same, so everything which affects the game, and nothing else, should be
+    /// hashed. Nothing is by default.
+    fn hash_state(&self, _hasher: &mut StateHasher) {}
 }
 
 
@@ -110,16 +423,17 @@ pub trait View {
 /// struct MyView;
 ///
 /// impl View for MyView {
-///     fn resume(&mut self, _: &mut Phi) {}
-///     fn pause(&mut self, _: &mut Phi) {}
-///     fn render(&mut self, context: &mut Phi, _: f64) -> ViewAction {
+///     fn update(&mut self, context: &mut Phi, _: f64) -> ViewAction {
 ///         if context.events.now.quit {
 ///             return ViewAction::Quit;
 ///         }
 ///
+///         ViewAction::None
+///     }
+///
+///     fn render(&self, context: &mut Phi, _: f64) {
 ///         context.renderer.set_draw_color(Color::RGB(255, 255, 0));
 ///         context.renderer.clear();
-///         ViewAction::None
 ///     }
 /// }
 ///
@@ -128,6 +442,12 @@ pub trait View {
 /// });
 /// ```
 pub fn spawn<F>(title: &str, init: F)
+where F: Fn(&mut Phi) -> Box<View> {
+    spawn_with(title, Config::new(), init)
+}
+
+/// Same as `spawn`, with the given options.
+pub fn spawn_with<F>(title: &str, config: Config, init: F)
 where F: Fn(&mut Phi) -> Box<View> {
     // Initialize SDL2
     let sdl_context = ::sdl2::init().unwrap();
@@ -136,64 +456,302 @@ where F: Fn(&mut Phi) -> Box<View> {
     let _ttf_context = ::sdl2_ttf::init();
 
     // Create the window
-    let window = video.window(title, 800, 600)
-        .position_centered().opengl().resizable()
+    let mut builder = video.window(title, config.window.width, config.window.height);
+    builder.position_centered().opengl();
+    let window = config.window.configure_window(&mut builder)
         .build().unwrap();
 
     // Create the context
-    let mut context = Phi::new(
-        Events::new(sdl_context.event_pump().unwrap()),
-        window.renderer()
-            .accelerated()
-            .build().unwrap());
+    let renderer = config.window.configure_renderer(window.renderer())
+        .target_texture()
+        .build().unwrap();
+    let min_frame_time = config.window.min_frame_time();
+    let (mut context, session) = create_context(&sdl_context, renderer, config);
 
     // Create the default view
-    let mut current_view = init(&mut context);
+    let first_view = init(&mut context);
+    let mut views = ViewStack::new(&mut context, first_view);
+    context.audio.switch_music(views.music());
 
 
     // Frame timing
-    let interval = 1_000 / 60;
-    let mut before = timer.ticks();
-    let mut last_second = timer.ticks();
+    let mut clock = FixedStep::new(session.step_rate);
+    let frequency = timer.performance_frequency() as f64;
+    let mut before = timer.performance_counter();
+    let mut last_second = before;
+    let mut last_frame = before;
     let mut fps = 0u16;
 
-    loop {
+    'running: loop {
         // Frame timing (bis)
 
-        let now = timer.ticks();
-        let dt = now - before;
-        let elapsed = dt as f64 / 1_000.0;
+        let now = timer.performance_counter();
+        let elapsed = (now - before) as f64 / frequency;
+        before = now;
 
-        // If the time elapsed since the last frame is too small, wait out the
-        // difference and try again.
-        if dt < interval {
-            timer.delay(interval - dt);
+        // If no step is due yet, wait until the next one instead of rendering
+        // the same frame again.
+        let steps = clock.advance(elapsed);
+        if steps == 0 {
+            timer.delay((clock.until_next() * 1_000.0) as u32);
             continue;
         }
 
-        before = now;
-        fps += 1;
+        // With a frame cap, frames which come too soon after the previous one
+        // are only simulated.
+        let rendered = match min_frame_time {
+            Some(min_frame_time) => (now - last_frame) as f64 / frequency >= min_frame_time,
+            None => true,
+        };
 
-        if now - last_second > 1_000 {
+        if rendered {
+            fps += 1;
+            last_frame = now;
+        }
+
+        if (now - last_second) as f64 > frequency {
             println!("FPS: {}", fps);
             last_second = now;
             fps = 0;
         }
 
 
-        // Logic & rendering
+        // Logic
+        //
+        // Every step lasts exactly `clock.dt()` seconds, so that the game
+        // behaves the same whatever the frame rate.
+
+        for _ in 0..steps {
+            if !step(&mut context, &mut views, clock.dt()) {
+                break 'running;
+            }
+        }
+
+
+        // Rendering
+
+        if !rendered {
+            continue;
+        }
+
+        render(&mut context, &mut views, clock.alpha());
+    }
+
+    shutdown(&mut context, &session);
+}
+
+
+/// Runs the game without showing anything nor playing any sound, for `steps`
+/// simulation steps, as fast as possible. SDL's dummy video driver and a
+/// software renderer are used, so that no display is needed. Once the steps
+/// were run, or the game quit, `inspect` is given the context and the view at
+/// the top of the stack, and what it returns is returned.
+///
+/// The sounds go to `config.audio`, except that `AudioOutput::Device` is
+/// treated as `AudioOutput::Null`. The input is usually given through
+/// `config.script`, since nobody can use the hidden window.
+pub fn spawn_headless<F, G, R>(mut config: Config, steps: u32, init: F, inspect: G) -> R
+where F: Fn(&mut Phi) -> Box<View>,
+      G: FnOnce(&mut Phi, Option<&View>) -> R {
+    // Must be set before the video subsystem is initialized.
+    ::std::env::set_var("SDL_VIDEODRIVER", "dummy");
+
+    let sdl_context = ::sdl2::init().unwrap();
+    let video = sdl_context.video().unwrap();
+    let _ttf_context = ::sdl2_ttf::init();
+
+    let window = video.window("phi", config.window.width, config.window.height)
+        .hidden()
+        .build().unwrap();
+
+    if config.audio == AudioOutput::Device {
+        config.audio = AudioOutput::Null;
+    }
+
+    let renderer = window.renderer()
+        .software()
+        .target_texture()
+        .build().unwrap();
+    let (mut context, session) = create_context(&sdl_context, renderer, config);
+
+    let first_view = init(&mut context);
+    let mut views = ViewStack::new(&mut context, first_view);
+    context.audio.switch_music(views.music());
 
-        context.events.pump(&mut context.renderer);
+    let dt = 1.0 / session.step_rate;
+    for _ in 0..steps {
+        if !step(&mut context, &mut views, dt) {
+            break;
+        }
+
+        // Render every step, so that drawing code runs as well.
+        render(&mut context, &mut views, 0.0);
+    }
+
+    shutdown(&mut context, &session);
+    inspect(&mut context, views.top())
+}
 
-        match current_view.render(&mut context, elapsed) {
-            ViewAction::None =>
-                context.renderer.present(),
 
-            ViewAction::Quit =>
-                break,
+/// Runs a single simulation step of `dt` seconds. Returns `false` once the
+/// game should stop.
+fn step(context: &mut Phi, views: &mut ViewStack, dt: f64) -> bool {
+    // The position of the mouse depends on where the logical screen lies.
+    context.fit_viewport();
+    context.events.pump(&mut context.renderer, &context.viewport);
 
-            ViewAction::ChangeView(new_view) =>
-                current_view = new_view,
+    for event in context.events.now.window_events() {
+        let action = views.on_window_event(context, event);
+        if !apply(context, views, action) {
+            return false;
         }
     }
+
+    let action = views.update(context, dt);
+
+    if context.trace.is_active() {
+        let mut hasher = StateHasher::new();
+        views.hash_state(&mut hasher);
+        context.trace.record(hasher.finish());
+    }
+
+    if let Err(e) = context.audio.advance(dt) {
+        println!("Could not render the audio: {}", e);
+    }
+
+    apply(context, views, action)
+}
+
+
+/// Applies the action requested by a view. Returns `false` once the game
+/// should stop.
+fn apply(context: &mut Phi, views: &mut ViewStack, action: ViewAction) -> bool {
+    if let ViewAction::None = action {
+        return true;
+    }
+
+    views.apply(context, action);
+
+    if views.is_empty() {
+        return false;
+    }
+
+    context.audio.switch_music(views.music());
+    true
+}
+
+
+/// Renders a frame, with black bars where the logical screen does not cover
+/// the window.
+fn render(context: &mut Phi, views: &mut ViewStack, alpha: f64) {
+    context.fit_viewport();
+    context.viewport.apply(&mut context.renderer);
+
+    // Clearing ignores the viewport, so this paints the bars as well.
+    context.renderer.set_draw_color(Color::RGB(0, 0, 0));
+    context.renderer.clear();
+
+    views.render(context, alpha);
+    context.renderer.present();
+}
+
+
+/// What the runners need to know about a session, besides the context.
+struct Session {
+    step_rate: f64,
+
+    /// Where the recording is saved, if any.
+    record: Option<String>,
+
+    /// Where the bindings are saved, if any.
+    bindings_path: Option<String>,
+}
+
+
+/// Creates the context in which the game runs, as described by `config`.
+fn create_context<'window>(sdl_context: &Sdl, renderer: Renderer<'window>, config: Config)
+        -> (Phi<'window>, Session) {
+    let replay = config.replay.map(Recording::replay);
+    let seed = match replay {
+        Some(ref replay) => replay.seed(),
+        None => config.seed.unwrap_or_else(random::time_seed),
+    };
+    let step_rate = replay.as_ref().map_or(config.step_rate, |replay| replay.step_rate());
+    println!("Seed: {}", seed);
+
+    // The recorded keys only mean the same with the same bindings.
+    let bindings = match replay {
+        Some(ref replay) => replay.bindings().clone(),
+        None => config.bindings,
+    };
+
+    // Scripted controllers must not open actual devices.
+    let scripted = config.script.is_some();
+    let mut events = Events::with_source(input_source(sdl_context, config.script));
+    if !scripted {
+        match sdl_context.game_controller() {
+            Ok(subsystem) => events.open_gamepads(subsystem),
+            Err(e) => println!("Controllers are unavailable: {}", e),
+        }
+    }
+    if let Some(replay) = replay {
+        events.replay(replay);
+    }
+    if config.record.is_some() {
+        events.record(Recording::new(seed, step_rate, bindings.clone()));
+    }
+
+    let audio = Audio::open(sdl_context, &config.audio).unwrap();
+    let viewport = Viewport::new(config.logical_size.0, config.logical_size.1, config.window.scaling);
+    let mut context = Phi::new(events, renderer, audio, config.window, viewport, seed);
+    context.bindings = bindings;
+
+    if let Some(ref path) = config.trace {
+        context.trace.write_to(path).unwrap();
+    }
+    if let Some(ref path) = config.verify {
+        context.trace.verify_against(path).unwrap();
+    }
+
+    (context, Session {
+        step_rate: step_rate,
+        record: config.record,
+        bindings_path: config.bindings_path,
+    })
+}
+
+
+/// Stops the session: the audio file, if any, is completed, and the input is
+/// saved if it was recorded. The trace is completed as well, and the bindings
+/// are saved if they changed.
+fn shutdown(context: &mut Phi, session: &Session) {
+    if let Err(e) = context.audio.finish() {
+        println!("Could not render the audio: {}", e);
+    }
+
+    context.trace.finish();
+
+    if let Some(ref path) = session.bindings_path {
+        if context.bindings.is_changed() {
+            if let Err(e) = context.bindings.save(path) {
+                println!("{}", e);
+            }
+        }
+    }
+
+    if let (Some(recording), Some(path)) = (context.events.take_recording(), session.record.as_ref()) {
+        match recording.save(path) {
+            Ok(()) => println!("Saved {} frames of input to {}", recording.frames(), path),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+
+/// Reads the events from `script` if there is one, otherwise from the window.
+fn input_source(sdl_context: &Sdl, script: Option<Script>) -> Box<InputSource> {
+    match script {
+        Some(script) => Box::new(script),
+        None => Box::new(sdl_context.event_pump().unwrap()),
+    }
 }
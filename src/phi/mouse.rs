@@ -0,0 +1,45 @@
+//! The mouse. Its position is given in logical pixels, so that views can
+//! compare it to what they draw, whatever the size of the window. On a touch
+//! screen, the first finger touching it acts as the left button.
+//!
+//! Buttons are designated by their index in `BUTTONS`, which is also how they
+//! are stored in replays.
+
+use ::sdl2::mouse::Mouse;
+
+
+pub const BUTTONS: &'static [Mouse] = &[
+    Mouse::Left, Mouse::Middle, Mouse::Right, Mouse::X1, Mouse::X2,
+];
+
+/// The index of the left button in `BUTTONS`, the one which clicks.
+pub const LEFT: usize = 0;
+
+/// The mouse SDL reports for the events it makes up from touches.
+pub const TOUCH_MOUSE_ID: u32 = 0xFFFF_FFFF;
+
+
+/// Where the mouse is, and which of its buttons are held down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cursor {
+    /// The position of the mouse on the logical screen. It stays on its edge
+    /// while the mouse is over the bars around it.
+    pub position: (f64, f64),
+    pub buttons: [bool; 5],
+}
+
+
+impl Cursor {
+    pub fn new() -> Cursor {
+        Cursor {
+            position: (0.0, 0.0),
+            buttons: [false; 5],
+        }
+    }
+}
+
+
+/// The index of `button` in `BUTTONS`, if it is one the game knows about.
+pub fn button_index(button: Mouse) -> Option<usize> {
+    BUTTONS.iter().position(|&b| b == button)
+}
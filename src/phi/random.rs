@@ -0,0 +1,82 @@
+//! Seeded random number generators, so that a run can be reproduced from the
+//! seed it was started with.
+
+use ::rand::{SeedableRng, XorShiftRng};
+
+
+/// Creates a random number generator whose output only depends on `seed`.
+pub fn seeded(seed: u64) -> XorShiftRng {
+    // The generator must not be seeded with zeros only.
+    XorShiftRng::from_seed([
+        seed as u32,
+        (seed >> 32) as u32,
+        0x9E37_79B9,
+        0x7F4A_7C15,
+    ])
+}
+
+
+/// A seed which is different on every run.
+pub fn time_seed() -> u64 {
+    let now = ::std::time::SystemTime::now()
+        .duration_since(::std::time::UNIX_EPOCH)
+        .unwrap();
+    now.as_secs() ^ ((now.subsec_nanos() as u64) << 32)
+}
+
+
+/// The independent sequences of random numbers available to the game. Drawing
+/// from one of them never changes what the others produce, so that cosmetic
+/// effects, for instance, may be added or removed without changing the
+/// asteroids spawned by a given seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// Decides what appears in the game, and when. Affects the gameplay.
+    Spawning = 0,
+
+    /// Only changes the way things look.
+    Cosmetics = 1,
+
+    /// Only changes the way things sound.
+    Audio = 2,
+}
+
+/// The number of variants of `Stream`.
+const STREAMS: usize = 3;
+
+
+/// A generator per `Stream`, all derived from a single seed.
+pub struct Random {
+    seed: u64,
+    streams: Vec<XorShiftRng>,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random {
+            seed: seed,
+            streams: (0..STREAMS as u64)
+                .map(|index| seeded(stream_seed(seed, index)))
+                .collect(),
+        }
+    }
+
+    /// The seed from which every stream was derived.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, stream: Stream) -> &mut XorShiftRng {
+        &mut self.streams[stream as usize]
+    }
+}
+
+
+/// Derives the seed of a stream from the main seed, so that streams do not
+/// produce the same numbers (SplitMix64).
+fn stream_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add((index + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,297 @@
+//! Recording of the input received on every simulation step, so that a session
+//! can be played back exactly, given the same seed and step rate.
+//!
+//! A replay file starts with a header:
+//!
+//! | bytes | contents                                  |
+//! |-------|-------------------------------------------|
+//! | 4     | `PHIR`                                    |
+//! | 2     | version of the format                     |
+//! | 8     | seed of the random number generators      |
+//! | 8     | number of steps per second, as an `f64`   |
+//! | 4     | number of frames                          |
+//! | 4     | length of the bindings, in bytes          |
+//! | n     | bindings, as in a bindings file           |
+//!
+//! The bindings are stored since the same keys may lead to different actions
+//! with other bindings.
+//!
+//! Then come the frames, one per step. Each of them is made of the number of
+//! entries it contains, on 2 bytes, followed by the entries, one byte each:
+//!
+//! - `2 * k + p` when the `k`-th key of `struct_events!` is pressed (`p = 1`)
+//!   or released (`p = 0`);
+//! - `ELSE_ENTRY + e` when the `e`-th other event happened;
+//! - `FOCUS_LOST_ENTRY` or `FOCUS_GAINED_ENTRY` when the window lost or got
+//!   the focus, and `MINIMIZED_ENTRY` or `RESTORED_ENTRY` when it was
+//!   minimized or restored;
+//! - `BUTTON_ENTRY`, followed by `2 * b + p` when the `b`-th button of
+//!   `gamepad::BUTTONS` is pressed or released;
+//! - `AXIS_ENTRY`, followed by the index of an axis in `gamepad::AXES` and its
+//!   new position, as an `i16`;
+//! - `MOUSE_MOTION_ENTRY`, followed by the new logical position of the mouse,
+//!   as two `f64`;
+//! - `MOUSE_BUTTON_ENTRY`, followed by `2 * b + p` when the `b`-th button of
+//!   `mouse::BUTTONS` is pressed or released;
+//! - `WHEEL_ENTRY`, followed by how far the wheel turned, as an `i16`;
+//! - `RESIZE_ENTRY`, followed by the new width and height of the window.
+//!
+//! Every number is little-endian.
+
+use super::ImmediateEvents;
+use super::actions::Bindings;
+use super::bytes::{le_bytes, le_value};
+use ::std::fs::File;
+use ::std::io::{Read, Write};
+
+
+const MAGIC: &'static [u8; 4] = b"PHIR";
+
+/// Increased whenever the format changes. Files with another version are
+/// rejected rather than played back wrongly.
+const VERSION: u16 = 1;
+
+/// The length of the header, up to the bindings.
+const HEADER_LEN: usize = 30;
+
+/// The code of the first event of the `else` section of `struct_events!`.
+pub const ELSE_ENTRY: u8 = 128;
+
+/// The code of a move of the mouse, or of the finger touching the screen.
+pub const MOUSE_MOTION_ENTRY: u8 = 246;
+/// The codes of the buttons and the wheel of the mouse.
+pub const MOUSE_BUTTON_ENTRY: u8 = 247;
+pub const WHEEL_ENTRY: u8 = 248;
+
+/// The codes of the input of the controllers.
+pub const BUTTON_ENTRY: u8 = 249;
+pub const AXIS_ENTRY: u8 = 250;
+
+/// The codes of the changes of the focus and state of the window.
+pub const FOCUS_LOST_ENTRY: u8 = 251;
+pub const FOCUS_GAINED_ENTRY: u8 = 252;
+pub const MINIMIZED_ENTRY: u8 = 253;
+pub const RESTORED_ENTRY: u8 = 254;
+
+/// The code of a resize of the window.
+pub const RESIZE_ENTRY: u8 = 255;
+
+
+/// The input of a session, frame by frame.
+pub struct Recording {
+    pub seed: u64,
+    pub step_rate: f64,
+
+    /// The bindings the input was recorded with.
+    pub bindings: Bindings,
+
+    frames: u32,
+
+    /// The encoded frames.
+    data: Vec<u8>,
+}
+
+
+/// Plays a `Recording` back, one frame at a time.
+pub struct Replay {
+    recording: Recording,
+
+    /// The offset of the next frame in the data of the recording.
+    position: usize,
+}
+
+
+impl Recording {
+    pub fn new(seed: u64, step_rate: f64, bindings: Bindings) -> Recording {
+        Recording {
+            seed: seed,
+            step_rate: step_rate,
+            bindings: bindings,
+            frames: 0,
+            data: vec![],
+        }
+    }
+
+    /// The number of frames recorded.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Adds the input received during a frame.
+    pub fn push(&mut self, now: &ImmediateEvents) {
+        now.encode(&mut self.data);
+        self.frames += 1;
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.data.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&le_bytes(VERSION as u64, 2));
+        bytes.extend_from_slice(&le_bytes(self.seed, 8));
+        bytes.extend_from_slice(&le_bytes(unsafe { ::std::mem::transmute::<f64, u64>(self.step_rate) }, 8));
+        bytes.extend_from_slice(&le_bytes(self.frames as u64, 4));
+
+        let bindings = self.bindings.encode();
+        bytes.extend_from_slice(&le_bytes(bindings.len() as u64, 4));
+        bytes.extend_from_slice(bindings.as_bytes());
+        bytes.extend_from_slice(&self.data);
+
+        File::create(path)
+            .and_then(|mut file| file.write_all(&bytes))
+            .map_err(|e| format!("Could not save the replay to {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Recording, String> {
+        let mut bytes = vec![];
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| format!("Could not read the replay {}: {}", path, e)));
+
+        Recording::decode(&bytes).map_err(|e| format!("Invalid replay {}: {}", path, e))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Recording, String> {
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+            return Err("Not a replay file".to_string());
+        }
+
+        let version = le_value(&bytes[4..6]) as u16;
+        if version != VERSION {
+            return Err(format!("Unsupported version {}, expected {}", version, VERSION));
+        }
+
+        let len = le_value(&bytes[HEADER_LEN - 4..HEADER_LEN]) as usize;
+        let data_start = HEADER_LEN + len;
+        if bytes.len() < data_start {
+            return Err("The bindings are truncated".to_string());
+        }
+
+        let contents = try!(::std::str::from_utf8(&bytes[HEADER_LEN..data_start])
+            .map_err(|_| "The bindings are not valid UTF-8".to_string()));
+
+        let recording = Recording {
+            seed: le_value(&bytes[6..14]),
+            step_rate: unsafe { ::std::mem::transmute::<u64, f64>(le_value(&bytes[14..22])) },
+            bindings: try!(Bindings::decode(contents, "bindings")),
+            frames: le_value(&bytes[22..26]) as u32,
+            data: bytes[data_start..].to_vec(),
+        };
+
+        // Check every frame now, so that a corrupted file is not noticed in
+        // the middle of playing it back.
+        let mut position = 0;
+        for frame in 0..recording.frames {
+            match ImmediateEvents::decode(&recording.data[position..]) {
+                Ok((_, read)) => position += read,
+                Err(e) => return Err(format!("Frame {}: {}", frame, e)),
+            }
+        }
+
+        Ok(recording)
+    }
+
+    pub fn replay(self) -> Replay {
+        Replay {
+            recording: self,
+            position: 0,
+        }
+    }
+}
+
+
+impl Replay {
+    pub fn seed(&self) -> u64 {
+        self.recording.seed
+    }
+
+    pub fn step_rate(&self) -> f64 {
+        self.recording.step_rate
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.recording.bindings
+    }
+
+    /// The input of the next frame, or `None` once the replay is over.
+    pub fn next_frame(&mut self) -> Option<ImmediateEvents> {
+        if self.is_over() {
+            return None;
+        }
+
+        let (now, read) = ImmediateEvents::decode(&self.recording.data[self.position..]).unwrap();
+        self.position += read;
+        Some(now)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.position >= self.recording.data.len()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::Recording;
+    use ::phi::ImmediateEvents;
+    use ::phi::actions::{Action, Bindings, Input};
+
+    fn frame() -> ImmediateEvents {
+        let mut now = ImmediateEvents::new();
+        now.key_space = Some(true);
+        now.key_escape = Some(false);
+        now.quit = true;
+        now.resize = Some((640, 480));
+        now.mouse_moved = Some((12.5, 300.0));
+        now.mouse_buttons = vec![(0, true), (2, false)];
+        now.wheel = -2;
+        now.focus = Some(false);
+        now
+    }
+
+    #[test]
+    fn encodes_and_decodes_a_frame() {
+        let mut bytes = vec![];
+        frame().encode(&mut bytes);
+        let len = bytes.len();
+
+        // The next frame starts right after.
+        ImmediateEvents::new().encode(&mut bytes);
+
+        let (now, read) = ImmediateEvents::decode(&bytes).unwrap();
+        assert_eq!(read, len);
+        assert_eq!(now.key_space, Some(true));
+        assert_eq!(now.key_escape, Some(false));
+        assert_eq!(now.key_up, None);
+        assert!(now.quit);
+        assert_eq!(now.resize, Some((640, 480)));
+        assert_eq!(now.mouse_moved, Some((12.5, 300.0)));
+        assert_eq!(now.mouse_buttons, vec![(0, true), (2, false)]);
+        assert_eq!(now.wheel, -2);
+        assert_eq!(now.focus, Some(false));
+        assert_eq!(now.minimized, None);
+
+        assert!(ImmediateEvents::decode(&bytes[..len - 1]).is_err());
+    }
+
+    #[test]
+    fn saves_and_loads_a_recording_with_its_bindings() {
+        let path = ::std::env::temp_dir().join("arcade-rs-replay.phir");
+        let path = path.to_str().unwrap();
+
+        let mut bindings = Bindings::new();
+        bindings.bind(Action::Fire, Input::from_name("Return").unwrap());
+
+        let mut recording = Recording::new(42, 120.0, bindings.clone());
+        recording.push(&ImmediateEvents::new());
+        recording.push(&frame());
+        recording.save(path).unwrap();
+
+        let mut replay = Recording::load(path).unwrap().replay();
+        assert_eq!((replay.seed(), replay.step_rate()), (42, 120.0));
+        assert_eq!(replay.bindings().inputs(Action::Fire), bindings.inputs(Action::Fire));
+        assert!(!replay.next_frame().unwrap().quit);
+        assert!(replay.next_frame().unwrap().quit);
+        assert!(replay.next_frame().is_none());
+    }
+}
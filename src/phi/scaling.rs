@@ -0,0 +1,150 @@
+//! The views draw into a screen of a fixed, logical size, whatever the size of
+//! the window. It is scaled up or down to fit the window, keeping its aspect
+//! ratio, and the rest of the window is left black.
+
+use ::sdl2::rect::Rect as SdlRect;
+use ::sdl2::render::Renderer;
+
+
+/// How the logical screen is scaled to the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleMode {
+    /// Every logical pixel becomes a square of the same number of physical
+    /// pixels, which keeps pixel art crisp but may leave wider bars. Used as
+    /// `Aspect` when the window is smaller than the logical screen.
+    Integer,
+
+    /// The logical screen is as large as the window allows.
+    Aspect,
+}
+
+
+/// Where the logical screen lies in the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub mode: ScaleMode,
+
+    /// The size of the logical screen.
+    logical: (u32, u32),
+
+    /// The number of physical pixels per logical pixel.
+    scale: f64,
+
+    /// The position of the top-left corner of the logical screen in the
+    /// window, in physical pixels.
+    offset: (f64, f64),
+
+    /// The size of the window, in physical pixels.
+    output: (u32, u32),
+}
+
+
+impl Viewport {
+    /// A logical screen of `width` by `height` pixels, which must still be
+    /// fitted to the window.
+    pub fn new(width: u32, height: u32, mode: ScaleMode) -> Viewport {
+        Viewport {
+            mode: mode,
+            logical: (width, height),
+            scale: 1.0,
+            offset: (0.0, 0.0),
+            output: (width, height),
+        }
+    }
+
+    pub fn logical_size(&self) -> (u32, u32) {
+        self.logical
+    }
+
+    /// The number of physical pixels per logical pixel.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Places the logical screen in a window of `width` by `height` pixels.
+    pub fn fit(&mut self, width: u32, height: u32) {
+        let (w, h) = (width as f64, height as f64);
+        let (logical_w, logical_h) = (self.logical.0 as f64, self.logical.1 as f64);
+
+        let largest = (w / logical_w).min(h / logical_h);
+        self.scale = match self.mode {
+            ScaleMode::Integer if largest >= 1.0 => largest.floor(),
+            ScaleMode::Integer | ScaleMode::Aspect => largest,
+        };
+
+        self.offset = ((w - logical_w * self.scale) / 2.0,
+                       (h - logical_h * self.scale) / 2.0);
+        self.output = (width, height);
+    }
+
+    /// Makes `renderer` draw into the logical screen. The renderer must draw
+    /// to the window, rather than to a texture.
+    pub fn apply(&self, renderer: &mut Renderer) {
+        // SDL expects the viewport in scaled coordinates.
+        renderer.set_scale(self.scale as f32, self.scale as f32);
+        renderer.set_viewport(SdlRect::new(
+            (self.offset.0 / self.scale) as i32,
+            (self.offset.1 / self.scale) as i32,
+            self.logical.0, self.logical.1).unwrap());
+    }
+
+    /// The logical position of the point at (`x`, `y`) in the window, or
+    /// `None` if it lies on the bars around the logical screen.
+    pub fn to_logical(&self, x: i32, y: i32) -> Option<(f64, f64)> {
+        let logical_x = (x as f64 - self.offset.0) / self.scale;
+        let logical_y = (y as f64 - self.offset.1) / self.scale;
+
+        if logical_x < 0.0 || logical_x >= self.logical.0 as f64 ||
+           logical_y < 0.0 || logical_y >= self.logical.1 as f64 {
+            None
+        } else {
+            Some((logical_x, logical_y))
+        }
+    }
+
+    /// The logical position of the point at (`x`, `y`) in the window, moved
+    /// to the nearest edge of the logical screen if it lies on the bars.
+    pub fn clamp_to_logical(&self, x: i32, y: i32) -> (f64, f64) {
+        let logical_x = (x as f64 - self.offset.0) / self.scale;
+        let logical_y = (y as f64 - self.offset.1) / self.scale;
+
+        (logical_x.max(0.0).min(self.logical.0 as f64 - 1.0),
+         logical_y.max(0.0).min(self.logical.1 as f64 - 1.0))
+    }
+
+    /// The logical position of a touch, given by SDL as fractions of the size
+    /// of the window.
+    pub fn touch_to_logical(&self, x: f32, y: f32) -> Option<(f64, f64)> {
+        self.to_logical((x as f64 * self.output.0 as f64) as i32,
+                        (y as f64 * self.output.1 as f64) as i32)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{ScaleMode, Viewport};
+
+    #[test]
+    fn letterboxes_a_wider_window() {
+        let mut viewport = Viewport::new(800, 600, ScaleMode::Aspect);
+        viewport.fit(1600, 900);
+
+        // Scaled by 1.5, with bars of 200 pixels on the sides.
+        assert_eq!(viewport.scale(), 1.5);
+        assert_eq!(viewport.to_logical(200, 0), Some((0.0, 0.0)));
+        assert_eq!(viewport.to_logical(800, 450), Some((400.0, 300.0)));
+        assert_eq!(viewport.to_logical(199, 450), None);
+        assert_eq!(viewport.to_logical(1400, 450), None);
+    }
+
+    #[test]
+    fn maps_touches_to_the_logical_screen() {
+        let mut viewport = Viewport::new(800, 600, ScaleMode::Integer);
+        viewport.fit(1000, 600);
+
+        assert_eq!(viewport.scale(), 1.0);
+        assert_eq!(viewport.touch_to_logical(0.5, 0.5), Some((400.0, 300.0)));
+        assert_eq!(viewport.touch_to_logical(0.05, 0.5), None);
+    }
+}
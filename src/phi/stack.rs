@@ -0,0 +1,341 @@
+use super::{Phi, View, ViewAction, WindowEvent};
+use super::audio::music::Playlist;
+use super::trace::StateHasher;
+use super::transition::Transition;
+use ::sdl2::pixels::PixelFormatEnum;
+use ::sdl2::render::Texture;
+
+
+/// The views currently shown, from the bottom to the top. Only the top one
+/// receives the input, but the ones below it may still be rendered and
+/// updated, depending on what the views above them allow.
+pub struct ViewStack {
+    views: Vec<Box<View>>,
+
+    /// The transition being played, if any.
+    leaving: Option<Leaving>,
+}
+
+
+/// The views a transition leads away from. They are still updated, without
+/// any input, and rendered until the transition ends.
+struct Leaving {
+    views: Vec<Box<View>>,
+    transition: Transition,
+
+    /// The time since the transition started, in seconds.
+    elapsed: f64,
+
+    /// The texture to which the incoming views are rendered, kept from a frame
+    /// to the next.
+    target: Option<Texture>,
+}
+
+
+impl ViewStack {
+    pub fn new(phi: &mut Phi, mut first: Box<View>) -> ViewStack {
+        first.on_enter(phi);
+        ViewStack {
+            views: vec![first],
+            leaving: None,
+        }
+    }
+
+    /// Whether the last view was popped, in which case the game should stop.
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// The view which receives the input.
+    pub fn top(&self) -> Option<&View> {
+        self.views.last().map(|view| &**view)
+    }
+
+    /// The playlist of the topmost view which has one, so that an overlay
+    /// without music of its own does not interrupt the music below it.
+    pub fn music(&self) -> Option<Playlist> {
+        self.views.iter().rev().filter_map(|view| view.music()).next()
+    }
+
+    /// Hashes the state of every view, from the bottom to the top, including
+    /// the ones a transition leads away from, since they are still updated.
+    pub fn hash_state(&self, hasher: &mut StateHasher) {
+        if let Some(ref leaving) = self.leaving {
+            hasher.write_usize(leaving.views.len());
+            for view in &leaving.views {
+                view.hash_state(hasher);
+            }
+        }
+
+        hasher.write_usize(self.views.len());
+        for view in &self.views {
+            view.hash_state(hasher);
+        }
+    }
+
+    /// Tells every view about a change to the window. Returns the action
+    /// requested by the top view.
+    pub fn on_window_event(&mut self, phi: &mut Phi, event: WindowEvent) -> ViewAction {
+        if let Some(ref mut leaving) = self.leaving {
+            for view in &mut leaving.views {
+                view.on_window_event(phi, event);
+            }
+        }
+
+        let mut action = ViewAction::None;
+        for view in &mut self.views {
+            action = view.on_window_event(phi, event);
+        }
+        action
+    }
+
+    /// Updates the top view, as well as the views below it which it lets be
+    /// updated. Covered views do not see the input. Returns the action
+    /// requested by the topmost view which requested one.
+    pub fn update(&mut self, phi: &mut Phi, dt: f64) -> ViewAction {
+        let finished = match self.leaving {
+            Some(ref mut leaving) => {
+                // What the outgoing views request is ignored: they are being
+                // replaced already.
+                let masked = phi.events.mask();
+                update_views(&mut leaving.views, phi, dt);
+                phi.events.unmask(masked);
+
+                leaving.elapsed += dt;
+                leaving.elapsed >= leaving.transition.duration
+            },
+            None => false,
+        };
+
+        if finished {
+            self.finish_transition(phi);
+        }
+
+        update_views(&mut self.views, phi, dt)
+    }
+
+    /// Renders the top view over the views below it which remain visible.
+    /// During a transition, the incoming views are revealed over the outgoing
+    /// ones.
+    pub fn render(&mut self, phi: &mut Phi, alpha: f64) {
+        // Taken out while the incoming views are rendered, and put back once
+        // the frame is drawn.
+        let mut leaving = match self.leaving.take() {
+            Some(leaving) => leaving,
+            None => {
+                render_views(&self.views, phi, alpha);
+                return;
+            },
+        };
+
+        let size = phi.viewport().logical_size();
+        let target = leaving.target.take()
+            .into_iter()
+            .filter(|texture| {
+                let query = texture.query();
+                (query.width, query.height) == size
+            })
+            .next()
+            .or_else(|| phi.renderer.create_texture_target(PixelFormatEnum::ARGB8888, size).ok());
+
+        // Without render targets, the transition is a plain cut.
+        let mut incoming = match target.and_then(|texture| render_to(phi, texture, &self.views, alpha)) {
+            Some(texture) => texture,
+            None => {
+                render_views(&self.views, phi, alpha);
+                self.leaving = Some(leaving);
+                return;
+            },
+        };
+
+        render_views(&leaving.views, phi, alpha);
+
+        let progress = leaving.transition.progress(leaving.elapsed);
+        leaving.transition.compose(&mut phi.renderer, &mut incoming, progress);
+        leaving.target = Some(incoming);
+        self.leaving = Some(leaving);
+    }
+
+    /// Changes the views according to `action`, calling their lifecycle hooks.
+    pub fn apply(&mut self, phi: &mut Phi, action: ViewAction) {
+        let updated_before = first_updated(&self.views);
+
+        // The number of views which are neither entered nor exited.
+        let kept = match action {
+            ViewAction::None =>
+                return,
+
+            ViewAction::Quit => {
+                self.clear(phi);
+                0
+            },
+
+            ViewAction::ChangeView(mut view) => {
+                self.clear(phi);
+                view.on_enter(phi);
+                self.views.push(view);
+                0
+            },
+
+            ViewAction::Transition(mut view, transition) => {
+                self.finish_transition(phi);
+                let views = ::std::mem::replace(&mut self.views, vec![]);
+
+                view.on_enter(phi);
+                self.views.push(view);
+
+                self.leaving = Some(Leaving {
+                    views: views,
+                    transition: transition,
+                    elapsed: 0.0,
+                    target: None,
+                });
+                0
+            },
+
+            ViewAction::Push(mut view) => {
+                let kept = self.views.len();
+                view.on_enter(phi);
+                self.views.push(view);
+                kept
+            },
+
+            ViewAction::Pop => {
+                pop(&mut self.views, phi);
+                self.views.len()
+            },
+
+            ViewAction::Replace(mut view) => {
+                pop(&mut self.views, phi);
+                let kept = self.views.len();
+                view.on_enter(phi);
+                self.views.push(view);
+                kept
+            },
+        };
+
+        // Tell the views which were kept whether they stopped or started
+        // being updated.
+        let updated_after = first_updated(&self.views);
+        for i in 0..kept {
+            let was_updated = i >= updated_before;
+            let is_updated = i >= updated_after;
+
+            if was_updated && !is_updated {
+                self.views[i].pause(phi);
+            } else if !was_updated && is_updated {
+                self.views[i].resume(phi);
+            }
+        }
+    }
+
+    /// Exits every view, from the top to the bottom.
+    pub fn clear(&mut self, phi: &mut Phi) {
+        self.finish_transition(phi);
+        while !self.views.is_empty() {
+            pop(&mut self.views, phi);
+        }
+    }
+
+    /// Ends the current transition, if any, exiting the outgoing views.
+    fn finish_transition(&mut self, phi: &mut Phi) {
+        if let Some(mut leaving) = self.leaving.take() {
+            while !leaving.views.is_empty() {
+                pop(&mut leaving.views, phi);
+            }
+        }
+    }
+}
+
+
+/// Updates the views of a stack, see `ViewStack::update`.
+fn update_views(views: &mut Vec<Box<View>>, phi: &mut Phi, dt: f64) -> ViewAction {
+    if views.is_empty() {
+        return ViewAction::None;
+    }
+
+    let top = views.len() - 1;
+    let mut action = ViewAction::None;
+
+    for i in first_updated(views)..(top + 1) {
+        let requested =
+            if i == top {
+                views[i].update(phi, dt)
+            } else {
+                let masked = phi.events.mask();
+                let requested = views[i].update(phi, dt);
+                phi.events.unmask(masked);
+                requested
+            };
+
+        if let ViewAction::None = requested {
+            continue;
+        }
+
+        action = requested;
+    }
+
+    action
+}
+
+
+/// Renders the views of a stack, see `ViewStack::render`.
+fn render_views(views: &[Box<View>], phi: &mut Phi, alpha: f64) {
+    for view in &views[first_rendered(views)..] {
+        view.render(phi, alpha);
+    }
+}
+
+
+/// Renders the views of a stack to `texture` instead of the screen. Returns
+/// `None` if the renderer does not support it.
+fn render_to(phi: &mut Phi, texture: Texture, views: &[Box<View>], alpha: f64) -> Option<Texture> {
+    match phi.renderer.render_target() {
+        Some(mut target) => {
+            if target.set(texture).is_err() {
+                return None;
+            }
+        },
+        None => return None,
+    }
+
+    render_views(views, phi, alpha);
+
+    phi.renderer.render_target()
+        .and_then(|mut target| target.reset().ok())
+        .and_then(|texture| texture)
+}
+
+
+fn pop(views: &mut Vec<Box<View>>, phi: &mut Phi) {
+    if let Some(mut view) = views.pop() {
+        view.on_exit(phi);
+    }
+}
+
+
+/// The index of the lowest view which is updated.
+fn first_updated(views: &[Box<View>]) -> usize {
+    first_where(views, |view| view.updates_below())
+}
+
+
+/// The index of the lowest view which is rendered.
+fn first_rendered(views: &[Box<View>]) -> usize {
+    first_where(views, |view| view.renders_below())
+}
+
+
+/// Goes down the stack from its top, for as long as `see_through` holds.
+fn first_where<F>(views: &[Box<View>], see_through: F) -> usize
+where F: Fn(&Box<View>) -> bool {
+    if views.is_empty() {
+        return 0;
+    }
+
+    let mut i = views.len() - 1;
+    while i > 0 && see_through(&views[i]) {
+        i -= 1;
+    }
+    i
+}
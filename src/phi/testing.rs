@@ -0,0 +1,52 @@
+//! Helpers for the tests which run the game headless, with scripted input.
+
+use super::{spawn_headless, AsAny, Config, Phi, View};
+use super::audio::AudioOutput;
+use super::input::Script;
+use ::std::any::Any;
+use ::std::sync::atomic::{AtomicBool, Ordering};
+use ::std::thread;
+
+
+/// SDL can only be initialized once at a time, while the tests run in
+/// parallel.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+
+/// Lets the next test use SDL when dropped, even if the test panicked.
+struct Running;
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+
+/// Runs without any sound, with a fixed seed, reading the input from
+/// `script`.
+pub fn config(script: Script) -> Config {
+    let mut config = Config::new();
+    config.audio = AudioOutput::Null;
+    config.seed = Some(1);
+    config.script = Some(script);
+    config
+}
+
+/// Same as `spawn_headless`, once the other tests using SDL are done.
+pub fn run<F, G, R>(config: Config, steps: u32, init: F, inspect: G) -> R
+where F: Fn(&mut Phi) -> Box<View>,
+      G: FnOnce(&mut Phi, Option<&View>) -> R {
+    while RUNNING.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        thread::yield_now();
+    }
+
+    let _running = Running;
+    spawn_headless(config, steps, init, inspect)
+}
+
+/// The view at the top of the stack, which must be a `T`.
+pub fn top<'a, T: Any>(view: Option<&'a View>) -> &'a T {
+    let view = view.expect("The game quit");
+    <View as AsAny>::as_any(view).downcast_ref::<T>().expect("Unexpected view at the top")
+}
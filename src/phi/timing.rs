@@ -0,0 +1,104 @@
+//! The simulation advances by steps of a fixed duration, whatever the frame
+//! rate, so that the game behaves the same on every machine.
+
+
+/// The default number of simulation steps per second.
+pub const DEFAULT_STEP_RATE: f64 = 120.0;
+
+/// The default maximum number of steps run to catch up in a single frame.
+pub const DEFAULT_MAX_STEPS: u32 = 8;
+
+
+/// Accumulates the real time which has passed, and tells how many fixed steps
+/// must be simulated to keep up with it.
+pub struct FixedStep {
+    /// The duration of a step, in seconds.
+    dt: f64,
+
+    /// After a stall (a slow frame, or the window being dragged), at most this
+    /// many steps are run in a row. The rest of the late time is forgotten,
+    /// rather than making the game even slower by trying to catch up.
+    pub max_steps: u32,
+
+    /// The time which has passed, but was not simulated yet.
+    accumulator: f64,
+}
+
+impl FixedStep {
+    /// Runs `rate` steps per second.
+    pub fn new(rate: f64) -> FixedStep {
+        FixedStep {
+            dt: 1.0 / rate,
+            max_steps: DEFAULT_MAX_STEPS,
+            accumulator: 0.0,
+        }
+    }
+
+    /// The duration of a step, in seconds.
+    pub fn dt(&self) -> f64 {
+        self.dt
+    }
+
+    /// Adds `elapsed` seconds of real time, and returns the number of steps
+    /// which must be simulated before rendering the next frame.
+    pub fn advance(&mut self, elapsed: f64) -> u32 {
+        self.accumulator += elapsed.max(0.0);
+
+        let mut steps = (self.accumulator / self.dt).floor() as u32;
+        if steps > self.max_steps {
+            println!("Skipping {} steps to catch up", steps - self.max_steps);
+            steps = self.max_steps;
+            self.accumulator = self.accumulator % self.dt + steps as f64 * self.dt;
+        }
+
+        self.accumulator -= steps as f64 * self.dt;
+        steps
+    }
+
+    /// How far the simulation is into the next step, between 0 and 1.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.dt
+    }
+
+    /// The time left before the next step is due, in seconds.
+    pub fn until_next(&self) -> f64 {
+        (self.dt - self.accumulator).max(0.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::FixedStep;
+
+    #[test]
+    fn counts_the_steps() {
+        let mut step = FixedStep::new(4.0);
+        assert_eq!(step.dt(), 0.25);
+        assert_eq!(step.advance(0.125), 0);
+        assert_eq!(step.advance(0.125), 1);
+        assert_eq!(step.advance(0.75), 3);
+        assert_eq!(step.advance(-1.0), 0);
+        assert_eq!(step.until_next(), 0.25);
+    }
+
+    #[test]
+    fn drops_the_time_beyond_max_steps() {
+        let mut step = FixedStep::new(4.0);
+        step.max_steps = 2;
+        assert_eq!(step.advance(2.125), 2);
+        assert_eq!(step.alpha(), 0.5);
+        assert_eq!(step.advance(0.125), 1);
+    }
+
+    #[test]
+    fn tells_how_far_into_the_next_step() {
+        let mut step = FixedStep::new(4.0);
+        assert_eq!(step.alpha(), 0.0);
+        step.advance(0.0625);
+        assert_eq!(step.alpha(), 0.25);
+        step.advance(0.3125);
+        assert_eq!(step.alpha(), 0.5);
+        assert_eq!(step.until_next(), 0.125);
+    }
+}
@@ -0,0 +1,223 @@
+//! Hashes of the state of the game after every simulation step. Written to a
+//! trace file, they tell whether a change to the code, or a replay, leads to
+//! exactly the same game as before, and if not, from which frame on.
+//!
+//! A trace file is a text file with one line per frame: its number, followed
+//! by the hash in hexadecimal.
+
+use ::phi::bytes::le_bytes;
+use ::phi::data::Rectangle;
+use ::std::fs::File;
+use ::std::io::{BufRead, BufReader, BufWriter, Write};
+
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+
+/// Computes a 64 bits FNV-1a hash. Unlike the hasher of the standard library,
+/// its result never changes between runs, versions or machines.
+pub struct StateHasher {
+    hash: u64,
+}
+
+
+/// Writes the hash of every frame to a trace file, and compares it to the one
+/// of a previous trace.
+pub struct Tracer {
+    frame: u64,
+    output: Option<(String, BufWriter<File>)>,
+
+    /// The hashes of the trace the game is compared to.
+    expected: Option<Vec<u64>>,
+
+    /// The first frame whose hash differed from the expected one.
+    divergence: Option<u64>,
+}
+
+
+impl StateHasher {
+    pub fn new() -> StateHasher {
+        StateHasher {
+            hash: FNV_OFFSET_BASIS,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write(&le_bytes(value, 8));
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    /// Hashes the exact bits of `value`, so that the slightest difference in
+    /// a position is noticed.
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(unsafe { ::std::mem::transmute::<f64, u64>(value) });
+    }
+
+    pub fn write_rect(&mut self, rect: Rectangle) {
+        self.write_f64(rect.x);
+        self.write_f64(rect.y);
+        self.write_f64(rect.w);
+        self.write_f64(rect.h);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+
+impl Tracer {
+    /// Neither writes nor verifies anything.
+    pub fn new() -> Tracer {
+        Tracer {
+            frame: 0,
+            output: None,
+            expected: None,
+            divergence: None,
+        }
+    }
+
+    /// Writes the hashes to the file at `path` from now on.
+    pub fn write_to(&mut self, path: &str) -> Result<(), String> {
+        let file = try!(File::create(path)
+            .map_err(|e| format!("Could not create the trace {}: {}", path, e)));
+        self.output = Some((path.to_string(), BufWriter::new(file)));
+        Ok(())
+    }
+
+    /// Compares the hashes to the ones of the trace at `path` from now on.
+    pub fn verify_against(&mut self, path: &str) -> Result<(), String> {
+        let file = try!(File::open(path)
+            .map_err(|e| format!("Could not open the trace {}: {}", path, e)));
+
+        let mut expected = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = try!(line.map_err(|e| e.to_string()));
+            let hash = line.split_whitespace().nth(1)
+                .and_then(|hash| u64::from_str_radix(hash, 16).ok());
+
+            match hash {
+                Some(hash) => expected.push(hash),
+                None => return Err(format!("Invalid line {} in the trace {}", i + 1, path)),
+            }
+        }
+
+        self.expected = Some(expected);
+        Ok(())
+    }
+
+    /// Whether the hashes are used at all, so that they need not be computed
+    /// otherwise.
+    pub fn is_active(&self) -> bool {
+        self.output.is_some() || self.expected.is_some()
+    }
+
+    /// Records the hash of the state at the end of the current frame, and
+    /// moves on to the next one.
+    pub fn record(&mut self, hash: u64) {
+        let frame = self.frame;
+        self.frame += 1;
+
+        if let Some((ref path, ref mut writer)) = self.output {
+            if let Err(e) = writeln!(writer, "{} {:016x}", frame, hash) {
+                println!("Could not write to the trace {}: {}", path, e);
+            }
+        }
+
+        if self.divergence.is_some() {
+            return;
+        }
+
+        let expected = match self.expected {
+            Some(ref expected) => expected.get(frame as usize).cloned(),
+            None => return,
+        };
+
+        if let Some(expected) = expected {
+            if expected != hash {
+                println!("Desync at frame {}: expected {:016x}, got {:016x}", frame, expected, hash);
+                self.divergence = Some(frame);
+            }
+        }
+    }
+
+    /// The first frame which did not match the expected trace, if any.
+    pub fn divergence(&self) -> Option<u64> {
+        self.divergence
+    }
+
+    /// Flushes the trace, and reports how the verification went.
+    pub fn finish(&mut self) {
+        if let Some((ref path, ref mut writer)) = self.output {
+            if let Err(e) = writer.flush() {
+                println!("Could not write to the trace {}: {}", path, e);
+            }
+        }
+
+        if let Some(ref expected) = self.expected {
+            match self.divergence {
+                Some(frame) => println!("The game diverged from the trace at frame {}", frame),
+                None if self.frame < expected.len() as u64 =>
+                    println!("The first {} frames match the trace, which has {}",
+                             self.frame, expected.len()),
+                None => println!("Every frame matches the trace"),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Tracer;
+    use ::std::fs::File;
+    use ::std::io::Write;
+
+    fn temp_path(name: &str) -> String {
+        ::std::env::temp_dir().join(name).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn reports_the_first_frame_which_differs() {
+        let written = temp_path("arcade-rs-written.trace");
+        let mut tracer = Tracer::new();
+        tracer.write_to(&written).unwrap();
+        for hash in 0..10 {
+            tracer.record(hash * 7);
+        }
+        tracer.finish();
+
+        // The same trace, with frames 4 and 6 altered.
+        let altered = temp_path("arcade-rs-altered.trace");
+        let mut file = File::create(&altered).unwrap();
+        for frame in 0..10 {
+            let hash = if frame == 4 || frame == 6 { 1 } else { frame * 7 };
+            writeln!(file, "{} {:016x}", frame, hash).unwrap();
+        }
+
+        let mut tracer = Tracer::new();
+        tracer.verify_against(&written).unwrap();
+        for hash in 0..10 {
+            tracer.record(hash * 7);
+        }
+        assert_eq!(tracer.divergence(), None);
+
+        let mut tracer = Tracer::new();
+        tracer.verify_against(&altered).unwrap();
+        for hash in 0..10 {
+            tracer.record(hash * 7);
+        }
+        assert_eq!(tracer.divergence(), Some(4));
+    }
+}
@@ -0,0 +1,194 @@
+//! Animated transitions from a view to another one. While a transition plays,
+//! both views are updated and rendered: the outgoing one is drawn first, then
+//! the incoming one is drawn to a texture, and progressively revealed over it.
+
+use ::phi::data::Rectangle;
+use ::sdl2::render::{BlendMode, Renderer, Texture};
+use ::std::f64::consts::PI;
+
+
+/// The size of the blocks in which the new view appears during a dissolve, in
+/// pixels.
+const DISSOLVE_BLOCK: f64 = 10.0;
+
+/// The height of the strips used to draw the circle of an iris, in pixels.
+const IRIS_STRIP: f64 = 4.0;
+
+
+/// The side of the screen from which a wipe starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+
+/// The way in which the new view replaces the old one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// The new view fades in over the old one.
+    Fade,
+
+    /// The new view is revealed by an edge sweeping the screen from a side.
+    Wipe(Side),
+
+    /// The new view is revealed by a circle growing from the center.
+    Iris,
+
+    /// The new view appears block by block, in a random-looking order.
+    Dissolve,
+}
+
+
+/// How the progress of a transition evolves over time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// Starts slowly, then accelerates.
+    EaseIn,
+    /// Starts quickly, then slows down.
+    EaseOut,
+    /// Starts and ends slowly.
+    EaseInOut,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub effect: Effect,
+
+    /// In seconds.
+    pub duration: f64,
+
+    pub easing: Easing,
+}
+
+
+impl Easing {
+    /// Maps the elapsed fraction of the duration, between 0 and 1, to the
+    /// fraction of the effect which should be shown.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => (1.0 - (PI * t).cos()) / 2.0,
+        }
+    }
+}
+
+
+impl Transition {
+    /// A transition which eases in and out.
+    pub fn new(effect: Effect, duration: f64) -> Transition {
+        Transition {
+            effect: effect,
+            duration: duration,
+            easing: Easing::EaseInOut,
+        }
+    }
+
+    pub fn with_easing(self, easing: Easing) -> Transition {
+        Transition { easing: easing, ..self }
+    }
+
+    /// The fraction of the new view which is shown `elapsed` seconds after the
+    /// transition started.
+    pub fn progress(&self, elapsed: f64) -> f64 {
+        if self.duration <= 0.0 { 1.0 }
+        else { self.easing.apply(elapsed / self.duration) }
+    }
+
+    /// Draws the part of `incoming`, which holds the rendering of the new view
+    /// and has the size of the logical screen, which is revealed at `progress`.
+    pub fn compose(&self, renderer: &mut Renderer, incoming: &mut Texture, progress: f64) {
+        let query = incoming.query();
+        let (w, h) = (query.width as f64, query.height as f64);
+
+        match self.effect {
+            Effect::Fade => {
+                incoming.set_blend_mode(BlendMode::Blend);
+                incoming.set_alpha_mod((progress * 255.0) as u8);
+                renderer.copy(incoming, None, None);
+                incoming.set_alpha_mod(255);
+                incoming.set_blend_mode(BlendMode::None);
+            },
+
+            Effect::Wipe(side) => {
+                let rect = match side {
+                    Side::Left => Rectangle { x: 0.0, y: 0.0, w: w * progress, h: h },
+                    Side::Right => Rectangle { x: w * (1.0 - progress), y: 0.0, w: w * progress, h: h },
+                    Side::Top => Rectangle { x: 0.0, y: 0.0, w: w, h: h * progress },
+                    Side::Bottom => Rectangle { x: 0.0, y: h * (1.0 - progress), w: w, h: h * progress },
+                };
+                copy_region(renderer, incoming, rect);
+            },
+
+            Effect::Iris => {
+                // The circle reaches the corners of the screen at the end.
+                let radius = (w * w + h * h).sqrt() / 2.0 * progress;
+                let (cx, cy) = (w / 2.0, h / 2.0);
+
+                let mut y = (cy - radius).max(0.0).floor();
+                while y < (cy + radius).min(h) {
+                    let dy = (y + IRIS_STRIP / 2.0 - cy).abs();
+                    if dy < radius {
+                        let half = (radius * radius - dy * dy).sqrt();
+                        copy_region(renderer, incoming, Rectangle {
+                            x: (cx - half).max(0.0),
+                            y: y,
+                            w: half * 2.0,
+                            h: IRIS_STRIP,
+                        });
+                    }
+                    y += IRIS_STRIP;
+                }
+            },
+
+            Effect::Dissolve => {
+                let columns = (w / DISSOLVE_BLOCK).ceil() as u32;
+                let rows = (h / DISSOLVE_BLOCK).ceil() as u32;
+
+                for row in 0..rows {
+                    for column in 0..columns {
+                        if dissolve_threshold(row * columns + column) < progress {
+                            copy_region(renderer, incoming, Rectangle {
+                                x: column as f64 * DISSOLVE_BLOCK,
+                                y: row as f64 * DISSOLVE_BLOCK,
+                                w: DISSOLVE_BLOCK,
+                                h: DISSOLVE_BLOCK,
+                            });
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+
+/// Copies `rect` from `texture` to the same place on the screen.
+fn copy_region(renderer: &mut Renderer, texture: &Texture, rect: Rectangle) {
+    // An empty rectangle would be converted to `None`, i.e. the whole texture.
+    if rect.w < 1.0 || rect.h < 1.0 {
+        return;
+    }
+
+    let rect = rect.to_sdl();
+    renderer.copy(texture, rect, rect);
+}
+
+
+/// The progress, between 0 and 1, from which the block at `index` is shown
+/// during a dissolve. Blocks are scattered by hashing their index, so that the
+/// pattern is the same every time.
+fn dissolve_threshold(index: u32) -> f64 {
+    let mut x = index.wrapping_mul(0x9E37_79B9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 13;
+    x as f64 / ::std::u32::MAX as f64
+}
@@ -0,0 +1,220 @@
+//! How the game's window is shown: its size, whether it covers the screen,
+//! and how it is drawn to.
+//!
+//! The options may be read from a file with one `key = value` pair per line,
+//! where blank lines and the ones starting with `#` are ignored:
+//!
+//! ```text
+//! # 1280x720, without borders, over the whole screen
+//! width = 1280
+//! height = 720
+//! mode = borderless
+//! vsync = true
+//! frame_cap = 60
+//! renderer = accelerated
+//! scaling = integer
+//! ```
+
+use ::phi::scaling::ScaleMode;
+use ::sdl2::video::{FullscreenType, WindowBuilder};
+use ::sdl2::render::{Renderer, RendererBuilder};
+use ::std::fs::File;
+use ::std::io::{BufRead, BufReader};
+
+
+/// Whether the window covers the whole screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowMode {
+    Windowed,
+
+    /// Changes the resolution of the screen to the size of the window.
+    Fullscreen,
+
+    /// A window without borders as large as the desktop, whose resolution is
+    /// left untouched.
+    Borderless,
+}
+
+
+/// How the game is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RendererKind {
+    /// Uses the graphics card.
+    Accelerated,
+
+    /// Draws on the processor, which is slow but works everywhere.
+    Software,
+}
+
+
+/// The options of the window, given to `spawn_with` through `Config`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    pub mode: WindowMode,
+
+    /// Whether frames are presented in sync with the refresh of the screen.
+    /// Cannot be changed once the game runs.
+    pub vsync: bool,
+
+    /// The maximum number of frames rendered every second, if any. The game
+    /// is never rendered more often than it is simulated anyway.
+    pub frame_cap: Option<u32>,
+
+    pub renderer: RendererKind,
+
+    /// How the logical screen of the game is scaled to the window.
+    pub scaling: ScaleMode,
+}
+
+
+impl WindowMode {
+    fn fullscreen_type(self) -> FullscreenType {
+        match self {
+            WindowMode::Windowed => FullscreenType::Off,
+            WindowMode::Fullscreen => FullscreenType::True,
+            WindowMode::Borderless => FullscreenType::Desktop,
+        }
+    }
+}
+
+
+impl WindowConfig {
+    /// An accelerated, resizable window of 800x600 pixels.
+    pub fn new() -> WindowConfig {
+        WindowConfig {
+            width: 800,
+            height: 600,
+            mode: WindowMode::Windowed,
+            vsync: false,
+            frame_cap: None,
+            renderer: RendererKind::Accelerated,
+            scaling: ScaleMode::Aspect,
+        }
+    }
+
+    /// Reads the options set in the file at `path`. The other ones keep their
+    /// default value.
+    pub fn load(path: &str) -> Result<WindowConfig, String> {
+        let file = try!(File::open(path)
+            .map_err(|e| format!("Could not open the window configuration {}: {}", path, e)));
+
+        let mut config = WindowConfig::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = try!(line.map_err(|e| e.to_string()));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap().trim();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => return Err(format!("{}:{}: Expected `key = value`", path, i + 1)),
+            };
+
+            try!(config.set(key, value).map_err(|e| format!("{}:{}: {}", path, i + 1, e)));
+        }
+
+        Ok(config)
+    }
+
+    /// Changes the option called `key`, as it is written in a configuration
+    /// file, to `value`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "width" => self.width = try!(parse_size(value)),
+            "height" => self.height = try!(parse_size(value)),
+            "mode" => self.mode = match value {
+                "windowed" => WindowMode::Windowed,
+                "fullscreen" => WindowMode::Fullscreen,
+                "borderless" => WindowMode::Borderless,
+                _ => return Err(format!("Unknown window mode: {}", value)),
+            },
+            "vsync" => self.vsync = try!(value.parse()
+                .map_err(|_| format!("Expected true or false, got {}", value))),
+            "frame_cap" => self.frame_cap = match try!(value.parse()
+                    .map_err(|_| format!("Expected a number of frames, got {}", value))) {
+                0 => None,
+                cap => Some(cap),
+            },
+            "renderer" => self.renderer = match value {
+                "accelerated" => RendererKind::Accelerated,
+                "software" => RendererKind::Software,
+                _ => return Err(format!("Unknown renderer: {}", value)),
+            },
+            "scaling" => self.scaling = match value {
+                "integer" => ScaleMode::Integer,
+                "aspect" => ScaleMode::Aspect,
+                _ => return Err(format!("Unknown scaling: {}", value)),
+            },
+            _ => return Err(format!("Unknown option: {}", key)),
+        }
+
+        Ok(())
+    }
+
+    /// Applies the size and mode to a window being built.
+    pub fn configure_window<'a>(&self, builder: &'a mut WindowBuilder) -> &'a mut WindowBuilder {
+        match self.mode {
+            WindowMode::Windowed => builder.resizable(),
+            WindowMode::Fullscreen => builder.fullscreen(),
+            WindowMode::Borderless => builder.fullscreen_desktop(),
+        }
+    }
+
+    /// Applies the renderer and vsync options to a renderer being built.
+    pub fn configure_renderer(&self, builder: RendererBuilder) -> RendererBuilder {
+        let builder = match self.renderer {
+            RendererKind::Accelerated => builder.accelerated(),
+            RendererKind::Software => builder.software(),
+        };
+
+        if self.vsync { builder.present_vsync() } else { builder }
+    }
+
+    /// The shortest time between two frames, in seconds, if there is one.
+    pub fn min_frame_time(&self) -> Option<f64> {
+        self.frame_cap.map(|cap| 1.0 / cap as f64)
+    }
+}
+
+
+/// Puts the window of `renderer` in `mode`.
+pub fn set_mode(renderer: &mut Renderer, mode: WindowMode) -> Result<(), String> {
+    match renderer.window_mut() {
+        Some(window) => window.set_fullscreen(mode.fullscreen_type()).map_err(|e| e.0),
+        None => Err("The renderer has no window".to_string()),
+    }
+}
+
+
+/// Resizes the window of `renderer`. In fullscreen mode, the resolution of the
+/// screen changes as well.
+pub fn set_size(renderer: &mut Renderer, mode: WindowMode, width: u32, height: u32) -> Result<(), String> {
+    let window = match renderer.window_mut() {
+        Some(window) => window,
+        None => return Err("The renderer has no window".to_string()),
+    };
+
+    window.set_size(width, height);
+
+    if mode == WindowMode::Fullscreen {
+        let mut display_mode = try!(window.display_mode().map_err(|e| e.0));
+        display_mode.w = width as i32;
+        display_mode.h = height as i32;
+        try!(window.set_display_mode(Some(display_mode)).map_err(|e| e.0));
+    }
+
+    Ok(())
+}
+
+
+fn parse_size(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(format!("Expected a positive number of pixels, got {}", value)),
+        Ok(size) => Ok(size),
+    }
+}
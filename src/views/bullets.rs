@@ -1,4 +1,5 @@
 use phi::Phi;
+use phi::audio::synth::SfxParams;
 use phi::data::Rectangle;
 use sdl2::pixels::Color;
 
@@ -62,6 +63,33 @@ pub trait Bullet {
 // ##############################################################
 // impls
 // ##############################################################
+impl CannonType {
+    /// The name under which the firing sound of the cannon is kept in the
+    /// `SoundBank`, and the parameters it is synthesized from. Seeds are fixed
+    /// so that a cannon always sounds the same.
+    pub fn firing_sound(&self) -> (&'static str, SfxParams) {
+        match *self {
+            CannonType::RectBullet =>
+                ("cannon_rect", SfxParams::laser(1)),
+
+            CannonType::SineBullet { .. } =>
+                ("cannon_sine", SfxParams {
+                    vibrato_depth: 0.2,
+                    vibrato_speed: 30.0,
+                    ..SfxParams::laser(2)
+                }),
+
+            CannonType::DivergentBullet { .. } =>
+                ("cannon_divergent", SfxParams {
+                    slide: -2.0,
+                    delta_slide: -20.0,
+                    ..SfxParams::laser(3)
+                }),
+        }
+    }
+}
+
+
 impl Bullet for DivergentBullet {
     fn update(mut self: Box<Self>, phi: &mut Phi, dt: f64) -> Option<Box<Bullet>> {
         self.total_time += dt;
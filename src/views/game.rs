@@ -1,7 +1,15 @@
-use ::phi::{Phi, View, ViewAction};
+use ::phi::actions::Action;
+use ::phi::{Phi, View, ViewAction, WindowEvent};
 use ::phi::data::{MaybeAlive, Rectangle};
 use ::phi::gfx::{AnimatedSprite, AnimatedSpriteDescr, CopySprite, Sprite};
-use ::phi::audio as Audio;
+use ::phi::mouse;
+use ::phi::audio::Bus;
+use ::phi::audio::bank::{Sound, SoundSettings};
+use ::phi::audio::music::{Playlist, Track};
+use ::phi::audio::synth::SfxParams;
+use ::phi::random::Stream;
+use ::phi::trace::StateHasher;
+use ::rand::Rng;
 use ::sdl2::pixels::Color;
 use ::sdl2::render::Renderer;
 use views::shared::BgSet;
@@ -22,6 +30,8 @@ const ASTEROIDS_WIDE: usize = 21;
 const ASTEROIDS_HIGH: usize = 7;
 const ASTEROIDS_TOTAL: usize = ASTEROIDS_WIDE * ASTEROIDS_HIGH - 4;
 const ASTEROID_SIDE: f64 = 96.0;
+/// The average number of asteroids appearing every second.
+const ASTEROID_SPAWN_RATE: f64 = 0.6;
 
 const EXPLOSION_PATH: &'static str = "assets/explosion.png";
 const EXPLOSION_AUDIO_PATH: &'static str = "assets/explosion.wav";
@@ -32,6 +42,37 @@ const EXPLOSION_SIDE: f64 = 96.0;
 const EXPLOSION_FPS: f64 = 16.0;
 const EXPLOSION_DURATION: f64 = 1.0 / EXPLOSION_FPS * EXPLOSIONS_TOTAL as f64;
 
+const DEBUG_FONT: &'static str = "assets/PressStart2P.ttf";
+const DEBUG_FONT_SIZE: i32 = 10;
+
+const GAME_MUSIC_INTRO_PATH: &'static str = "assets/music/game_intro.ogg";
+const GAME_MUSIC_PATH: &'static str = "assets/music/game.ogg";
+
+/// A spread of bullets may destroy many asteroids at once. Only a few of
+/// their explosions are heard, and never twice in the same instant.
+const EXPLOSION_SOUND: SoundSettings = SoundSettings {
+    bus: Bus::Sfx,
+    max_voices: 4,
+    priority: 5,
+    cooldown: 0.05,
+};
+
+/// The player fires often, so its cannons must never drown the explosions.
+const FIRING_SOUND: SoundSettings = SoundSettings {
+    bus: Bus::Sfx,
+    max_voices: 2,
+    priority: 1,
+    cooldown: 0.0,
+};
+
+/// The number of slightly detuned versions of the firing sounds, picked at
+/// random so that rapid fire does not sound mechanical.
+const FIRING_VARIANTS: u64 = 4;
+const FIRING_DETUNE: f64 = 0.03;
+
+/// The sounds which must be in the `SoundBank` before a `GameView` is created.
+pub const SOUNDS: &'static [&'static str] = &[EXPLOSION_AUDIO_PATH];
+
 
 /// The different states our ship might be in. In the image, they're ordered
 /// from left to right, then from top to bottom.
@@ -81,7 +122,11 @@ pub struct GameView {
     asteroid_factory: AsteroidFactory,
     explosions: Vec<Explosion>,
     explosion_factory: ExplosionFactory,
+    explosion_sound: Sound,
     bg: BgSet,
+
+    /// Shows the seed of the run, so that it can be reproduced.
+    seed_sprite: Sprite,
 }
 
 
@@ -147,7 +192,8 @@ impl AsteroidFactory {
         let (w, h) = phi.output_size();
 
         let mut sprite = self.sprite.clone();
-        sprite.set_fps(::rand::random::<f64>().abs() * 20.0 + 10.0);
+        sprite.set_fps(phi.random.stream(Stream::Cosmetics).gen::<f64>() * 20.0 + 10.0);
+        let rng = phi.random.stream(Stream::Spawning);
 
         Asteroid {
             sprite: sprite,
@@ -155,9 +201,9 @@ impl AsteroidFactory {
                 w: ASTEROID_SIDE,
                 h: ASTEROID_SIDE,
                 x: w,
-                y: ::rand::random::<f64>().abs() * (h - ASTEROID_SIDE),
+                y: rng.gen::<f64>() * (h - ASTEROID_SIDE),
             },
-            vel: ::rand::random::<f64>().abs() * 100.0 + 50.0,
+            vel: rng.gen::<f64>() * 100.0 + 50.0,
         }
     }
 }
@@ -198,7 +244,7 @@ impl Explosion {
 
 impl ExplosionFactory {
     fn at_center(&self, center: (f64, f64)) -> Explosion {
-        let mut sprite = self.sprite.clone();
+        let sprite = self.sprite.clone();
 
         Explosion {
             sprite: sprite,
@@ -227,23 +273,44 @@ impl GameView {
             asteroid_factory: Asteroid::factory(phi),
             explosions: vec![],
             explosion_factory: Explosion::factory(phi),
+            explosion_sound: phi.sounds.get(EXPLOSION_AUDIO_PATH)
+                .expect("The explosion sound was not preloaded")
+                .with_settings(EXPLOSION_SOUND),
             bg: bg,
+            seed_sprite: phi.ttf_str_sprite(&format!("Seed: {}", phi.seed()), DEBUG_FONT,
+                                            DEBUG_FONT_SIZE, Color::RGB(200, 200, 50)).unwrap(),
         }
     }
+
+    /// Where the player's ship is.
+    pub fn player_rect(&self) -> Rectangle {
+        self.player.rect
+    }
+
+    /// Where the bullets in flight are.
+    pub fn bullet_rects(&self) -> Vec<Rectangle> {
+        self.bullets.iter().map(|bullet| bullet.rect()).collect()
+    }
 }
 
 impl View for GameView {
-    fn render(&mut self, phi: &mut Phi, elapsed: f64) -> ViewAction {
+    fn update(&mut self, phi: &mut Phi, elapsed: f64) -> ViewAction {
         if phi.events.now.quit {
             return ViewAction::Quit;
         }
 
-        if phi.events.now.key_escape == Some(true) {
-            return ViewAction::ChangeView(Box::new(
-                ::views::main_menu::MainMenuView::with_backgrounds(
-                    phi, self.bg.clone())));
+        if phi.pressed(Action::Pause) {
+            return ViewAction::Push(Box::new(
+                ::views::pause::PauseView::new(phi, self.bg.clone())));
         }
 
+        // Explosions are panned across the screen, and get quieter the farther
+        // they are from the player's ship.
+        phi.audio.spatial.width = phi.output_size().0;
+        phi.audio.spatial.listener = Some(self.player.rect.center());
+
+        self.bg.update(elapsed);
+
         self.bullets = ::std::mem::replace(&mut self.bullets, vec![])
             .into_iter()
             .filter_map(|bullet| bullet.update(phi, elapsed))
@@ -286,7 +353,8 @@ impl View for GameView {
                 if asteroid_alive {
                     Some(asteroid)
                 } else {
-                    Audio::playback_for(EXPLOSION_AUDIO_PATH);
+                    phi.audio.play_at(&self.explosion_sound,
+                                      asteroid.rect().center());
                     self.explosions.push(
                         self.explosion_factory.at_center(
                             asteroid.rect().center()));
@@ -303,21 +371,48 @@ impl View for GameView {
             println!("The player's ship has been destroyed!");
         }
 
-        if phi.events.now.key_space == Some(true) {
+        let clicked = phi.bindings.follow_mouse && phi.events.now.clicked(mouse::LEFT);
+        if phi.pressed(Action::Fire) || clicked {
             self.bullets.append(&mut self.player.spawn_bullets());
+
+            let (name, params) = self.player.cannon.firing_sound();
+            let variant = phi.random.stream(Stream::Audio).gen_range(0, FIRING_VARIANTS);
+            let params = SfxParams {
+                frequency: params.frequency * (1.0 + FIRING_DETUNE * variant as f64),
+                ..params
+            };
+
+            let sound = phi.sounds.synthesize(&format!("{}-{}", name, variant), &params)
+                .with_settings(FIRING_SOUND);
+            phi.audio.play_at(&sound, self.player.rect.center());
         }
 
-        if ::rand::random::<usize>() % 100 == 0 {
+        if phi.random.stream(Stream::Spawning).gen::<f64>() < ASTEROID_SPAWN_RATE * elapsed {
             self.asteroids.push(self.asteroid_factory.random(phi));
         }
 
+        // Sticks move the ship slower when they are only pushed partially.
+        let mut movement = (
+            phi.strength(Action::MoveRight) - phi.strength(Action::MoveLeft),
+            phi.strength(Action::MoveDown) - phi.strength(Action::MoveUp));
+
+        // The other inputs take over the mouse while they are used.
+        if phi.bindings.follow_mouse && movement == (0.0, 0.0) {
+            movement = self.player.toward(phi.events.cursor.position, elapsed);
+        }
+        self.player.update(phi, elapsed, movement);
+
+        ViewAction::None
+    }
+
+    fn render(&self, phi: &mut Phi, _: f64) {
         // Clear the scene
         phi.renderer.set_draw_color(Color::RGB(0, 0, 0));
         phi.renderer.clear();
 
         // Render the Backgrounds
-        self.bg.back.render(&mut phi.renderer, elapsed);
-        self.bg.middle.render(&mut phi.renderer, elapsed);
+        self.bg.back.render(phi);
+        self.bg.middle.render(phi);
 
         for asteroid in &self.asteroids {
             asteroid.render(phi);
@@ -331,13 +426,61 @@ impl View for GameView {
             explosion.render(phi);
         }
 
-        self.player.update(phi, elapsed);
         self.player.render(phi);
 
         // Render the foreground
-        self.bg.front.render(&mut phi.renderer, elapsed);
+        self.bg.front.render(phi);
 
-        ViewAction::None
+        if DEBUG {
+            let (w, h) = self.seed_sprite.size();
+            phi.renderer.copy_sprite(&self.seed_sprite, Rectangle {
+                w: w,
+                h: h,
+                x: 8.0,
+                y: 8.0,
+            });
+        }
+    }
+
+    fn on_window_event(&mut self, phi: &mut Phi, event: WindowEvent) -> ViewAction {
+        // The game stops as soon as the player cannot see it anymore. Since it
+        // is not updated while paused, the time spent away is not simulated.
+        match event {
+            WindowEvent::FocusLost | WindowEvent::Minimized =>
+                ViewAction::Push(Box::new(
+                    ::views::pause::PauseView::new(phi, self.bg.clone()))),
+            _ => ViewAction::None,
+        }
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        hasher.write_rect(self.player.rect);
+
+        hasher.write_usize(self.bullets.len());
+        for bullet in &self.bullets {
+            hasher.write_rect(bullet.rect());
+        }
+
+        hasher.write_usize(self.asteroids.len());
+        for asteroid in &self.asteroids {
+            hasher.write_rect(asteroid.rect);
+            hasher.write_f64(asteroid.vel);
+        }
+
+        hasher.write_usize(self.explosions.len());
+        for explosion in &self.explosions {
+            hasher.write_rect(explosion.rect);
+            hasher.write_f64(explosion.alive_since);
+        }
+    }
+
+    fn music(&self) -> Option<Playlist> {
+        // The intro is played once, then the main theme loops until the
+        // player leaves the game.
+        Some(Playlist::new("game", vec![
+            Track::new(GAME_MUSIC_INTRO_PATH),
+            Track::new(GAME_MUSIC_PATH).looping(),
+        ]))
     }
 }
 
@@ -406,45 +549,47 @@ impl Player {
         Bullet::spawn_bullets(self.cannon, cannons_x, cannon1_y, cannon2_y)
     }
 
-    pub fn update(&mut self, phi: &mut Phi, elapsed: f64) {
-        if phi.events.now.key_1 == Some(true) {
+    /// The movement which brings the center of the ship to `target` as fast
+    /// as it can go, to be given to `update`.
+    pub fn toward(&self, target: (f64, f64), elapsed: f64) -> (f64, f64) {
+        let (x, y) = self.rect.center();
+        let step = PLAYER_SPEED * elapsed;
+        if step <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        ((target.0 - x) / step, (target.1 - y) / step)
+    }
+
+    /// Moves the ship in the direction of `movement`, whose coordinates are
+    /// between -1 and 1, at a speed proportional to its length.
+    pub fn update(&mut self, phi: &mut Phi, elapsed: f64, movement: (f64, f64)) {
+        if phi.pressed(Action::SelectWeapon1) {
             self.cannon = Bullet::CannonType::RectBullet;
         }
 
-        if phi.events.now.key_2 == Some(true) {
+        if phi.pressed(Action::SelectWeapon2) {
             self.cannon = Bullet::CannonType::SineBullet {
                 amplitude: 10.0,
                 angular_vel: 15.0,
             };
         }
 
-        if phi.events.now.key_3 == Some(true) {
+        if phi.pressed(Action::SelectWeapon3) {
             self.cannon = Bullet::CannonType::DivergentBullet {
                 a: 100.0,
                 b: 1.2,
             };
         }
 
-        // Move the player's ship
-        let diagonal =
-            (phi.events.key_up ^ phi.events.key_down) &&
-            (phi.events.key_left ^ phi.events.key_right);
+        // Move the player's ship. Moving diagonally must not be faster than
+        // moving straight.
+        let (x, y) = movement;
+        let length = (x * x + y * y).sqrt();
+        let scale = if length > 1.0 { 1.0 / length } else { 1.0 };
 
-        let moved =
-            if diagonal { 1.0 / 2.0f64.sqrt() }
-            else { 1.0 } * PLAYER_SPEED * elapsed;
-
-        let dx = match (phi.events.key_left, phi.events.key_right) {
-            (true, true) | (false, false) => 0.0,
-            (true, false) => -moved,
-            (false, true) => moved,
-        };
-
-        let dy = match (phi.events.key_up, phi.events.key_down) {
-            (true, true) | (false, false) => 0.0,
-            (true, false) => -moved,
-            (false, true) => moved,
-        };
+        let dx = x * scale * PLAYER_SPEED * elapsed;
+        let dy = y * scale * PLAYER_SPEED * elapsed;
 
         self.rect.x += dx;
         self.rect.y += dy;
@@ -481,3 +626,102 @@ impl Player {
 
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Asteroid, GameView, PLAYER_SPEED, SOUNDS};
+    use ::phi::{Phi, View};
+    use ::phi::data::Rectangle;
+    use ::phi::input::{self, Script};
+    use ::phi::testing;
+    use ::phi::timing::DEFAULT_STEP_RATE;
+    use ::sdl2::controller::Axis;
+    use ::sdl2::keyboard::Keycode;
+    use views::bullets as Bullet;
+    use views::bullets::{BULLET_H, BULLET_W};
+
+    fn game(phi: &mut Phi) -> Box<View> {
+        phi.sounds.preload(SOUNDS).unwrap();
+        Box::new(GameView::new(phi))
+    }
+
+    /// A game where a still asteroid is in the way of a single bullet, which
+    /// reaches it during the tenth step.
+    fn asteroid_in_the_way(phi: &mut Phi) -> Box<View> {
+        phi.sounds.preload(SOUNDS).unwrap();
+        let mut game = GameView::new(phi);
+
+        game.asteroids.push(Asteroid {
+            sprite: game.asteroid_factory.sprite.clone(),
+            rect: Rectangle { x: 300.0, y: 450.0, w: super::ASTEROID_SIDE, h: super::ASTEROID_SIDE },
+            vel: 0.0,
+        });
+
+        let mut bullets = Bullet::spawn_bullets(Bullet::CannonType::RectBullet, 245.0, 490.0, 0.0);
+        bullets.truncate(1);
+        game.bullets = bullets;
+
+        Box::new(game)
+    }
+
+    #[test]
+    fn destroying_an_asteroid_plays_one_explosion() {
+        let (log, explosion) = testing::run(testing::config(Script::new()), 20, asteroid_in_the_way, |phi, _| {
+            (phi.audio.voice_log().to_vec(), phi.sounds.get(super::EXPLOSION_AUDIO_PATH).unwrap())
+        });
+
+        // The mixer renders 367.5 frames per step, of which 3307 were
+        // rendered after the first nine steps.
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].sound, explosion.id());
+        assert_eq!((log[0].step, log[0].frame), (9, 3_307));
+    }
+
+    /// How far the ship moves down in `STEPS` steps, with the left stick
+    /// pushed down to `value` from the start.
+    fn moved_down(value: i16) -> f64 {
+        let player_y = |script| testing::run(testing::config(script), STEPS, game, |_, view| {
+            testing::top::<GameView>(view).player_rect().y
+        });
+
+        player_y(Script::new().at(0, input::axis_motion(Axis::LeftY, value))) - player_y(Script::new())
+    }
+
+    const STEPS: u32 = 60;
+
+    #[test]
+    fn partial_stick_deflection_moves_slower() {
+        let full_speed = PLAYER_SPEED * STEPS as f64 / DEFAULT_STEP_RATE;
+
+        // Pushed 62.5% of the way, past the default deadzone of 25%: half of
+        // the remaining course.
+        let moved = moved_down(20_479);
+        assert!(moved > 0.0 && moved < full_speed);
+        assert!((moved - full_speed / 2.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn stick_inside_deadzone_does_not_move() {
+        assert_eq!(moved_down(6_553), 0.0);
+    }
+
+    #[test]
+    fn space_fires_two_rect_bullets() {
+        let script = Script::new()
+            .at(3, input::key_down(Keycode::Space))
+            .at(4, input::key_up(Keycode::Space));
+
+        let bullets = testing::run(testing::config(script), 6, game, |_, view| {
+            testing::top::<GameView>(view).bullet_rects()
+        });
+
+        // The default cannon shoots straight, from both wings at once.
+        assert_eq!(bullets.len(), 2);
+        assert_eq!(bullets[0].x, bullets[1].x);
+        assert!(bullets[0].y != bullets[1].y);
+        for bullet in &bullets {
+            assert_eq!((bullet.w, bullet.h), (BULLET_W, BULLET_H));
+        }
+    }
+}
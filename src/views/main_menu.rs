@@ -1,24 +1,31 @@
+use ::phi::actions::Action;
+use ::phi::audio::music::{Playlist, Track};
 use ::phi::data::Rectangle;
 use ::phi::gfx::{CopySprite, Sprite};
+use ::phi::mouse;
 use ::phi::{Phi, View, ViewAction};
+use ::phi::transition::{Effect, Side, Transition};
 use ::sdl2::pixels::Color;
 use ::views::shared::BgSet;
 
 const MENU_FONT: &'static str = "assets/PressStart2P.ttf";
 const MENU_HOVER_SIZE: i32 = 24;
 const MENU_IDLE_SIZE: i32 = 18;
+const MENU_MUSIC_PATH: &'static str = "assets/music/menu.ogg";
+const NEW_GAME_TRANSITION: f64 = 0.8;
+const LABEL_H: f64 = 40.0;
 
 
-struct Action {
+struct MenuEntry {
     func: Box<Fn(&mut Phi, BgSet) -> ViewAction>,
     idle_sprite: Sprite,
     hover_sprite: Sprite,
 }
 
 
-impl Action {
-    fn new(phi: &mut Phi, label: &'static str, func: Box<Fn(&mut Phi, BgSet) -> ViewAction>) -> Action {
-        Action {
+impl MenuEntry {
+    fn new(phi: &mut Phi, label: &'static str, func: Box<Fn(&mut Phi, BgSet) -> ViewAction>) -> MenuEntry {
+        MenuEntry {
             func: func,
             idle_sprite: phi.ttf_str_sprite(label, MENU_FONT,
                                             MENU_IDLE_SIZE, Color::RGB(220, 220, 220)).unwrap(),
@@ -29,7 +36,7 @@ impl Action {
 }
 
 pub struct MainMenuView {
-    actions: Vec<Action>,
+    entries: Vec<MenuEntry>,
     // using i8 instead of usize so that we don't have underflow errors
     // when decrementing it on key_up
     selected: i8,
@@ -45,12 +52,13 @@ impl MainMenuView {
 
     pub fn with_backgrounds(phi: &mut Phi, bg: BgSet) -> MainMenuView {
         MainMenuView {
-            actions: vec![
-                Action::new(phi, "New Game", Box::new(|phi, bg| {
-                    ViewAction::ChangeView(Box::new(
-                        ::views::game::GameView::with_backgrounds(phi, bg)))
+            entries: vec![
+                MenuEntry::new(phi, "New Game", Box::new(|phi, bg| {
+                    ViewAction::Transition(Box::new(
+                        ::views::game::GameView::with_backgrounds(phi, bg)),
+                        Transition::new(Effect::Wipe(Side::Right), NEW_GAME_TRANSITION))
                 })),
-                Action::new(phi, "Quit", Box::new(|_, _| {
+                MenuEntry::new(phi, "Quit", Box::new(|_, _| {
                     ViewAction::Quit
                 })),
             ],
@@ -59,45 +67,89 @@ impl MainMenuView {
             bg: bg,
         }
     }
+
+    /// Where the label of every entry is drawn, which is also where it can be
+    /// clicked.
+    fn entry_rects(&self, phi: &Phi) -> Vec<Rectangle> {
+        let (win_w, win_h) = phi.output_size();
+        let box_h = self.entries.len() as f64 * LABEL_H;
+
+        self.entries.iter().enumerate().map(|(i, entry)| {
+            let (w, h) =
+                if self.selected as usize == i { entry.hover_sprite.size() }
+                else { entry.idle_sprite.size() };
+
+            Rectangle {
+                w: w,
+                h: h,
+                x: (win_w - w) / 2.0,
+                y: (win_h - box_h + LABEL_H) / 2.0 + LABEL_H * i as f64,
+            }
+        }).collect()
+    }
 }
 
 
 impl View for MainMenuView {
-    fn render(&mut self, phi: &mut Phi, elapsed: f64) -> ViewAction {
-        if phi.events.now.quit || phi.events.now.key_escape == Some(true) {
+    fn update(&mut self, phi: &mut Phi, elapsed: f64) -> ViewAction {
+        // The backgrounds keep scrolling, so that they do not jump when they
+        // are handed to the game.
+        self.bg.update(elapsed);
+
+        if phi.events.now.quit || phi.pressed(Action::Back) {
             return ViewAction::Quit;
         }
 
+        if phi.pressed(Action::ToggleFullscreen) {
+            if let Err(e) = phi.toggle_fullscreen() {
+                println!("Could not toggle fullscreen: {}", e);
+            }
+        }
+
+        // Pointing at an entry selects it, and clicking it chooses it.
+        let cursor = phi.events.cursor.position;
+        let pointed = self.entry_rects(phi).iter().position(|rect| rect.contains_point(cursor));
+        let clicked = pointed.is_some() && phi.events.now.clicked(mouse::LEFT);
+        if let Some(i) = pointed {
+            if phi.events.now.mouse_moved.is_some() || clicked {
+                self.selected = i as i8;
+            }
+        }
 
-        if phi.events.now.key_space == Some(true) ||
-           phi.events.now.key_enter == Some(true) {
+        if phi.pressed(Action::Confirm) || clicked {
             let bg = self.bg.clone();
-            return (self.actions[self.selected as usize].func)(phi, bg);
+            return (self.entries[self.selected as usize].func)(phi, bg);
         }
 
-        if phi.events.now.key_up == Some(true) {
+        if phi.pressed(Action::MoveUp) {
             self.selected -= 1;
             if self.selected < 0 {
-                self.selected = self.actions.len() as i8 -1;
+                self.selected = self.entries.len() as i8 -1;
             }
         }
 
-        if phi.events.now.key_down == Some(true) {
+        if phi.pressed(Action::MoveDown) {
             self.selected += 1;
-            if self.selected >= self.actions.len() as i8 {
+            if self.selected >= self.entries.len() as i8 {
                 self.selected = 0;
             }
         }
 
+        ViewAction::None
+    }
 
+    fn render(&self, phi: &mut Phi, _: f64) {
         phi.renderer.set_draw_color(Color::RGB(0, 0, 0));
         phi.renderer.clear();
 
+        self.bg.back.render(phi);
+        self.bg.middle.render(phi);
+        self.bg.front.render(phi);
+
         let (win_w, win_h) = phi.output_size();
-        let label_h = 40.0;
         let border_width = 3.0;
         let box_w = 360.0;
-        let box_h = self.actions.len() as f64 * label_h;
+        let box_h = self.entries.len() as f64 * LABEL_H;
         let margin_h = 10.0;
 
         phi.renderer.set_draw_color(Color::RGB(70, 15, 70));
@@ -116,27 +168,19 @@ impl View for MainMenuView {
             y: (win_h - box_h) / 2.0 - margin_h,
         }.to_sdl().unwrap());
 
-        for (i, action) in self.actions.iter().enumerate() {
+        let rects = self.entry_rects(phi);
+        for (i, (entry, rect)) in self.entries.iter().zip(rects).enumerate() {
             if self.selected as usize == i {
-                let (w, h) = action.hover_sprite.size();
-                phi.renderer.copy_sprite(&action.hover_sprite, Rectangle {
-                    w: w,
-                    h: h,
-                    x: (win_w - w) / 2.0,
-                    y: (win_h - box_h + label_h) / 2.0 + label_h * i as f64,
-                });
+                phi.renderer.copy_sprite(&entry.hover_sprite, rect);
             } else {
-                let (w, h) = action.idle_sprite.size();
-                phi.renderer.copy_sprite(&action.idle_sprite, Rectangle {
-                    w: w,
-                    h: h,
-                    x: (win_w - w) / 2.0,
-                    y: (win_h - box_h + label_h) / 2.0 + label_h * i as f64,
-            });
+                phi.renderer.copy_sprite(&entry.idle_sprite, rect);
             }
         }
+    }
 
-
-        ViewAction::None
+    fn music(&self) -> Option<Playlist> {
+        Some(Playlist::new("menu", vec![
+            Track::new(MENU_MUSIC_PATH).looping(),
+        ]))
     }
 }
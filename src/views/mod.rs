@@ -1,4 +1,5 @@
 pub mod game;
 pub mod main_menu;
+pub mod pause;
 pub mod shared;
 pub mod bullets;
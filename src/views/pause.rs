@@ -0,0 +1,187 @@
+use ::phi::actions::Action;
+use ::phi::data::Rectangle;
+use ::phi::gfx::{CopySprite, Sprite};
+use ::phi::mouse;
+use ::phi::{Phi, View, ViewAction};
+use ::phi::transition::{Effect, Transition};
+use ::sdl2::pixels::Color;
+use ::sdl2::render::BlendMode;
+use ::views::shared::BgSet;
+
+const PAUSE_FONT: &'static str = "assets/PressStart2P.ttf";
+const PAUSE_TITLE_SIZE: i32 = 32;
+const PAUSE_HOVER_SIZE: i32 = 24;
+const PAUSE_IDLE_SIZE: i32 = 18;
+const MAIN_MENU_TRANSITION: f64 = 0.6;
+const LABEL_H: f64 = 40.0;
+
+
+/// What happens when an entry of the pause menu is chosen.
+#[derive(Clone, Copy, PartialEq)]
+enum Choice {
+    Resume,
+    MainMenu,
+    Quit,
+}
+
+
+struct Entry {
+    choice: Choice,
+    idle_sprite: Sprite,
+    hover_sprite: Sprite,
+}
+
+
+/// Shown over the game when the player presses Escape. The game is still
+/// drawn below it, but frozen until the player resumes it.
+pub struct PauseView {
+    title: Sprite,
+    entries: Vec<Entry>,
+    selected: usize,
+
+    /// The backgrounds of the game, handed to the main menu if the player
+    /// leaves the game.
+    bg: BgSet,
+}
+
+
+impl Entry {
+    fn new(phi: &mut Phi, label: &'static str, choice: Choice) -> Entry {
+        Entry {
+            choice: choice,
+            idle_sprite: phi.ttf_str_sprite(label, PAUSE_FONT,
+                                            PAUSE_IDLE_SIZE, Color::RGB(220, 220, 220)).unwrap(),
+            hover_sprite: phi.ttf_str_sprite(label, PAUSE_FONT,
+                                             PAUSE_HOVER_SIZE, Color::RGB(255, 255, 255)).unwrap(),
+        }
+    }
+}
+
+
+impl PauseView {
+    pub fn new(phi: &mut Phi, bg: BgSet) -> PauseView {
+        PauseView {
+            title: phi.ttf_str_sprite("Paused", PAUSE_FONT,
+                                      PAUSE_TITLE_SIZE, Color::RGB(255, 255, 255)).unwrap(),
+            entries: vec![
+                Entry::new(phi, "Resume", Choice::Resume),
+                Entry::new(phi, "Main Menu", Choice::MainMenu),
+                Entry::new(phi, "Quit", Choice::Quit),
+            ],
+            selected: 0,
+            bg: bg,
+        }
+    }
+
+    /// The top of the first entry.
+    fn top(&self, phi: &Phi) -> f64 {
+        (phi.output_size().1 - LABEL_H * self.entries.len() as f64) / 2.0
+    }
+
+    /// Where the label of every entry is drawn, which is also where it can be
+    /// clicked.
+    fn entry_rects(&self, phi: &Phi) -> Vec<Rectangle> {
+        let win_w = phi.output_size().0;
+        let top = self.top(phi);
+
+        self.entries.iter().enumerate().map(|(i, entry)| {
+            let (w, h) =
+                if self.selected == i { entry.hover_sprite.size() }
+                else { entry.idle_sprite.size() };
+
+            Rectangle {
+                w: w,
+                h: h,
+                x: (win_w - w) / 2.0,
+                y: top + LABEL_H * i as f64 + (LABEL_H - h) / 2.0,
+            }
+        }).collect()
+    }
+}
+
+
+impl View for PauseView {
+    fn update(&mut self, phi: &mut Phi, _: f64) -> ViewAction {
+        if phi.events.now.quit {
+            return ViewAction::Quit;
+        }
+
+        if phi.pressed(Action::Pause) || phi.pressed(Action::Back) {
+            return ViewAction::Pop;
+        }
+
+        if phi.pressed(Action::ToggleFullscreen) {
+            if let Err(e) = phi.toggle_fullscreen() {
+                println!("Could not toggle fullscreen: {}", e);
+            }
+        }
+
+        // Pointing at an entry selects it, and clicking it chooses it.
+        let cursor = phi.events.cursor.position;
+        let pointed = self.entry_rects(phi).iter().position(|rect| rect.contains_point(cursor));
+        let clicked = pointed.is_some() && phi.events.now.clicked(mouse::LEFT);
+        if let Some(i) = pointed {
+            if phi.events.now.mouse_moved.is_some() || clicked {
+                self.selected = i;
+            }
+        }
+
+        if phi.pressed(Action::Confirm) || clicked {
+            return match self.entries[self.selected].choice {
+                Choice::Resume => ViewAction::Pop,
+                Choice::MainMenu => ViewAction::Transition(Box::new(
+                    ::views::main_menu::MainMenuView::with_backgrounds(
+                        phi, self.bg.clone())),
+                    Transition::new(Effect::Iris, MAIN_MENU_TRANSITION)),
+                Choice::Quit => ViewAction::Quit,
+            };
+        }
+
+        if phi.pressed(Action::MoveUp) {
+            self.selected = (self.selected + self.entries.len() - 1) % self.entries.len();
+        }
+
+        if phi.pressed(Action::MoveDown) {
+            self.selected = (self.selected + 1) % self.entries.len();
+        }
+
+        ViewAction::None
+    }
+
+    fn render(&self, phi: &mut Phi, _: f64) {
+        let (win_w, win_h) = phi.output_size();
+
+        // Darken the game below.
+        phi.renderer.set_blend_mode(BlendMode::Blend);
+        phi.renderer.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        phi.renderer.fill_rect(Rectangle {
+            w: win_w,
+            h: win_h,
+            x: 0.0,
+            y: 0.0,
+        }.to_sdl().unwrap());
+        phi.renderer.set_blend_mode(BlendMode::None);
+
+        let (w, h) = self.title.size();
+        let top = self.top(phi);
+        phi.renderer.copy_sprite(&self.title, Rectangle {
+            w: w,
+            h: h,
+            x: (win_w - w) / 2.0,
+            y: top - h - LABEL_H,
+        });
+
+        let rects = self.entry_rects(phi);
+        for (i, (entry, rect)) in self.entries.iter().zip(rects).enumerate() {
+            let sprite =
+                if self.selected == i { &entry.hover_sprite }
+                else { &entry.idle_sprite };
+
+            phi.renderer.copy_sprite(sprite, rect);
+        }
+    }
+
+    fn renders_below(&self) -> bool {
+        true
+    }
+}
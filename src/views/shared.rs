@@ -1,3 +1,4 @@
+use ::phi::Phi;
 use ::phi::data::Rectangle;
 use ::phi::gfx::{CopySprite, Sprite};
 use ::sdl2::render::Renderer;
@@ -13,29 +14,33 @@ pub struct Background {
 
 
 impl Background {
-    pub fn render(&mut self, renderer: &mut Renderer, elapsed: f64) {
+    pub fn update(&mut self, elapsed: f64) {
         // we define a logical position as depending solely on the time and the
         // dimensions of the image, not on the screen's size.
-       let size = self.sprite.size();
+        let size = self.sprite.size();
         self.pos += self.vel * elapsed;
         if self.pos > size.0 {
             self.pos -= size.0;
         }
+    }
+
+    pub fn render(&self, phi: &mut Phi) {
+        let size = self.sprite.size();
 
-        // we determine the scale ratio of the window to the spirte
-        let (win_w, win_h) = renderer.output_size().unwrap();
-        let scale = win_h as f64 / size.1;
+        // we determine the scale ratio of the screen to the spirte
+        let (win_w, win_h) = phi.output_size();
+        let scale = win_h / size.1;
 
         // we render as many copies of the background as necessary
         // to fill the screen
         let mut physical_left = -self.pos * scale;
 
-        while physical_left < win_w as f64 {
-            renderer.copy_sprite(&self.sprite, Rectangle {
+        while physical_left < win_w {
+            phi.renderer.copy_sprite(&self.sprite, Rectangle {
                 x: physical_left,
                 y: 0.0,
                 w: size.0 * scale,
-                h: win_h as f64,
+                h: win_h,
             });
 
             physical_left += size.0 * scale;
@@ -71,4 +76,10 @@ impl BgSet {
             },
         }
     }
+
+    pub fn update(&mut self, elapsed: f64) {
+        self.back.update(elapsed);
+        self.middle.update(elapsed);
+        self.front.update(elapsed);
+    }
 }